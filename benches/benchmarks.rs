@@ -1,5 +1,5 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use gtypist_rs::{Script, Executor};
+use gtypist_rs::{Script, Executor, MemoryBackend};
 use std::fs;
 use tempfile::NamedTempFile;
 use std::io::Write;
@@ -46,7 +46,9 @@ X:
     
     c.bench_function("execute_commands", |b| {
         b.iter(|| {
-            let mut executor = Executor::new(script.clone());
+            let mut backend = MemoryBackend::new();
+            backend.push_text("  ");
+            let mut executor = Executor::with_backend(script.clone(), Box::new(backend));
             while !executor.script.is_finished() {
                 let _ = executor.execute_next();
             }
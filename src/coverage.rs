@@ -0,0 +1,269 @@
+//! Lesson coverage from execution traces
+//!
+//! Replays the traces written with `--trace` against a lesson to show
+//! which of its labels were reached and which exercises were attempted
+//! and completed, as an outline of the lesson under its labels and `B:`
+//! banners.
+
+use std::fmt;
+use std::path::Path;
+use crate::exercises::ExerciseOutcome;
+use crate::script::Script;
+use crate::script::commands::Command;
+use crate::script::trace::{TraceEvent, TraceRecord};
+use crate::script::writer::write_command;
+
+/// Characters of exercise text shown in the outline
+const PREVIEW_WIDTH: usize = 50;
+
+/// How far a learner got with one command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    NotReached,
+    /// Reached, but never completed if it is an exercise
+    Reached,
+    Completed,
+}
+
+impl Status {
+    fn marker(self) -> &'static str {
+        match self {
+            Status::NotReached => "[ ]",
+            Status::Reached => "[~]",
+            Status::Completed => "[x]",
+        }
+    }
+}
+
+/// What a set of traces covered of one lesson
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coverage {
+    /// Times each command was executed
+    visits: Vec<usize>,
+    /// Exercise outcomes recorded for each command
+    attempts: Vec<usize>,
+    /// Completed outcomes recorded for each command
+    completions: Vec<usize>,
+    /// Trace files added
+    pub traces: usize,
+    /// Events that don't fit this lesson, e.g. from an older version of it
+    pub mismatched: usize,
+}
+
+impl Coverage {
+    /// Coverage of a lesson before any trace is added
+    pub fn new(script: &Script) -> Self {
+        let length = script.commands.len();
+        Self {
+            visits: vec![0; length],
+            attempts: vec![0; length],
+            completions: vec![0; length],
+            traces: 0,
+            mismatched: 0,
+        }
+    }
+
+    /// Count the events of one trace
+    ///
+    /// A command event only counts if the lesson has the same command at
+    /// that index, so traces of other lessons add nothing.
+    pub fn add_trace(&mut self, script: &Script, records: &[TraceRecord]) {
+        self.traces += 1;
+        for record in records {
+            match &record.event {
+                TraceEvent::Command { index, command, .. } => {
+                    if script.commands.get(*index) == Some(command) {
+                        self.visits[*index] += 1;
+                    } else {
+                        self.mismatched += 1;
+                    }
+                },
+                TraceEvent::Outcome { index, outcome, .. } => {
+                    if self.visits.get(*index).is_some_and(|&visits| visits > 0) {
+                        self.attempts[*index] += 1;
+                        if matches!(outcome, ExerciseOutcome::Completed(_)) {
+                            self.completions[*index] += 1;
+                        }
+                    } else {
+                        self.mismatched += 1;
+                    }
+                },
+                TraceEvent::Jump { .. } => {},
+            }
+        }
+    }
+
+    /// How far the traces got with the command at `index`
+    pub fn status(&self, script: &Script, index: usize) -> Status {
+        if self.visits[index] == 0 {
+            Status::NotReached
+        } else if is_exercise(&script.commands[index]) && self.completions[index] == 0 {
+            Status::Reached
+        } else {
+            Status::Completed
+        }
+    }
+
+    /// Totals for the whole lesson
+    pub fn summary(&self, script: &Script) -> Summary {
+        let mut summary = Summary::default();
+        for (index, command) in script.commands.iter().enumerate() {
+            let status = self.status(script, index);
+            match command {
+                Command::Label { .. } => {
+                    summary.labels += 1;
+                    summary.labels_reached += usize::from(status != Status::NotReached);
+                },
+                command if is_exercise(command) => {
+                    summary.exercises += 1;
+                    summary.exercises_reached += usize::from(status != Status::NotReached);
+                    summary.exercises_completed += usize::from(status == Status::Completed);
+                },
+                _ => {},
+            }
+        }
+        summary
+    }
+
+    /// The lesson as an outline: labels, `B:` banners beneath them and
+    /// every exercise, each marked with how far the traces got
+    pub fn outline(&self, script: &Script) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (index, command) in script.commands.iter().enumerate() {
+            let status = self.status(script, index);
+            match command {
+                Command::Label { name } => {
+                    if !lines.is_empty() {
+                        lines.push(String::new());
+                    }
+                    let reached = if status == Status::NotReached { "never reached" } else { "reached" };
+                    lines.push(format!("{} *:{}  ({}, {})", status.marker(), name, location(script, index), reached));
+                },
+                Command::Clear { banner: Some(banner) } => {
+                    lines.push(format!("      B: {}", banner.trim()));
+                },
+                command if is_exercise(command) => {
+                    let text = write_command(command)
+                        .map(|text| text.lines().next().unwrap_or_default().to_string())
+                        .unwrap_or_else(|_| format!("{:?}", command));
+                    let mut line = format!("    {} {:<width$}  {}", status.marker(), preview(&text), location(script, index),
+                        width = PREVIEW_WIDTH);
+                    if self.attempts[index] > 0 && !matches!(command, Command::Tutorial { .. } | Command::TutorialFile { .. }) {
+                        line.push_str(&format!(", {}/{} completed", self.completions[index], self.attempts[index]));
+                    }
+                    lines.push(line);
+                },
+                _ => {},
+            }
+        }
+        lines
+    }
+}
+
+/// Label and exercise counts of a [`Coverage`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub labels: usize,
+    pub labels_reached: usize,
+    pub exercises: usize,
+    pub exercises_reached: usize,
+    pub exercises_completed: usize,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{} labels reached, {}/{} exercises completed ({} reached)",
+            self.labels_reached, self.labels, self.exercises_completed, self.exercises, self.exercises_reached)
+    }
+}
+
+fn is_exercise(command: &Command) -> bool {
+    matches!(command,
+        Command::Tutorial { .. } | Command::Drill { .. } | Command::SpeedTest { .. } |
+        Command::TutorialFile { .. } | Command::DrillFile { .. } | Command::SpeedTestFile { .. })
+}
+
+/// Source line of a command, with the file name if it came from an include
+fn location(script: &Script, index: usize) -> String {
+    match script.span(index) {
+        Some(span) if span.file == script.path => format!("line {}", span.line),
+        Some(span) => {
+            let file = Path::new(&span.file).file_name().map_or(span.file.clone(), |f| f.to_string_lossy().into_owned());
+            format!("{}:{}", file, span.line)
+        },
+        None => format!("command {}", index),
+    }
+}
+
+/// Exercise text cut down to one outline column, runs of spaces and
+/// tabs shown as a single space
+fn preview(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= PREVIEW_WIDTH {
+        return text;
+    }
+    let mut preview: String = text.chars().take(PREVIEW_WIDTH - 3).collect();
+    preview.push_str("...");
+    preview
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner;
+    use crate::script::executor::Executor;
+    use crate::script::trace::{self, TraceSink};
+    use tempfile::TempDir;
+
+    const LESSON: &str = "*:START\nB:First steps\nT:Welcome\nD:ab\n*:MORE\nB:Further\nD:cd\nX:\n";
+
+    #[test]
+    fn test_outline_marks_reached_and_completed() {
+        let dir = TempDir::new().unwrap();
+        let lesson = dir.path().join("lesson.typ");
+        std::fs::write(&lesson, LESSON).unwrap();
+        let lesson = lesson.to_str().unwrap();
+        let trace_path = dir.path().join("trace.jsonl");
+        let trace_path = trace_path.to_str().unwrap();
+
+        // Read the tutorial, then leave during the first drill
+        let events = runner::parse_key_events("0 <Space>\n100 a\n200 <Esc>\n").unwrap();
+        let mut executor = Executor::with_backend(Script::from_file(lesson).unwrap(), Box::new(runner::backend_from_events(events)))
+            .with_trace(TraceSink::create(trace_path).unwrap());
+        runner::run(&mut executor).unwrap();
+
+        let script = Script::from_file(lesson).unwrap();
+        let mut coverage = Coverage::new(&script);
+        coverage.add_trace(&script, &trace::read_trace(trace_path).unwrap());
+
+        let outline = coverage.outline(&script);
+        assert!(outline[0].starts_with("[x] *:START  (line 1, reached)"), "{:?}", outline);
+        assert_eq!(outline[1], "      B: First steps");
+        assert!(outline[2].starts_with("    [x] T:Welcome "));
+        assert!(outline[3].starts_with("    [~] D:ab ") && outline[3].ends_with("line 4, 0/1 completed"), "{:?}", outline);
+        assert_eq!(outline[4], "");
+        assert!(outline[5].starts_with("[ ] *:MORE  (line 5, never reached)"));
+        assert!(outline[7].starts_with("    [ ] D:cd ") && outline[7].ends_with("line 7"));
+
+        assert_eq!(coverage.summary(&script).to_string(), "1/2 labels reached, 1/3 exercises completed (2 reached)");
+        assert_eq!(coverage.mismatched, 0);
+    }
+
+    #[test]
+    fn test_other_lessons_do_not_count() {
+        let dir = TempDir::new().unwrap();
+        let lesson = dir.path().join("lesson.typ");
+        std::fs::write(&lesson, LESSON).unwrap();
+        let script = Script::from_file(lesson.to_str().unwrap()).unwrap();
+
+        let record = TraceRecord {
+            time: String::new(),
+            event: TraceEvent::Command { index: 0, span: None, command: Command::Label { name: "OTHER".to_string() } },
+        };
+        let mut coverage = Coverage::new(&script);
+        coverage.add_trace(&script, &[record]);
+
+        assert_eq!(coverage.status(&script, 0), Status::NotReached);
+        assert_eq!(coverage.mismatched, 1);
+    }
+}
@@ -0,0 +1,288 @@
+//! Step debugger for lesson authors
+//!
+//! In debug mode execution pauses before every command, or only at
+//! breakpoint labels once the author continues. While paused the screen
+//! shows the commands around the current one, with a side panel of the
+//! executor's state, and the author can step, skip the current command,
+//! jump to any label or toggle breakpoints from a label picker.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+use crossterm::{
+    QueueableCommand,
+    cursor::MoveTo,
+    event::{Event, KeyCode, KeyEvent},
+};
+use crate::script::ScriptResult;
+use crate::script::commands::Command;
+use crate::script::executor::Executor;
+use crate::script::writer::write_command;
+
+/// Columns taken by the state panel on the right
+const PANEL_WIDTH: usize = 32;
+
+const HELP: &str = "Enter/s step  c continue  k skip  g go to label  b breakpoint  q quit";
+
+/// What to do after the debugger hands control back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugAction {
+    /// Execute the current command
+    Run,
+    /// Stop the lesson
+    Quit,
+}
+
+/// Breakpoints and stepping state of a debugging session
+#[derive(Debug, Clone, Default)]
+pub struct Debugger {
+    /// Labels to pause at
+    pub breakpoints: BTreeSet<String>,
+    /// Pause before every command rather than only at breakpoints
+    pub stepping: bool,
+}
+
+impl Debugger {
+    pub fn new(breakpoints: impl IntoIterator<Item = String>, stepping: bool) -> Self {
+        Self { breakpoints: breakpoints.into_iter().collect(), stepping }
+    }
+
+    fn at_breakpoint(&self, executor: &Executor) -> bool {
+        matches!(executor.script.current_command(), Some(Command::Label { name }) if self.breakpoints.contains(name))
+    }
+
+    /// Pause before the next command when stepping or at a breakpoint,
+    /// handling the author's keys until they run the command or quit
+    pub fn before_command(&mut self, executor: &mut Executor) -> ScriptResult<DebugAction> {
+        if !self.stepping && !self.at_breakpoint(executor) {
+            return Ok(DebugAction::Run);
+        }
+        self.stepping = true;
+
+        while !executor.script.is_finished() {
+            self.draw(executor)?;
+            let Event::Key(KeyEvent { code, .. }) = executor.backend.read_event()? else { continue };
+            match code {
+                KeyCode::Enter | KeyCode::Char('s') => return Ok(DebugAction::Run),
+                KeyCode::Char('c') => {
+                    self.stepping = false;
+                    return Ok(DebugAction::Run);
+                },
+                KeyCode::Char('k') => {
+                    executor.script.next();
+                },
+                KeyCode::Char('g') => {
+                    if let Some(label) = self.pick_label(executor, "Go to label")? {
+                        executor.script.goto_label(&label)?;
+                    }
+                },
+                KeyCode::Char('b') => {
+                    if let Some(label) = self.pick_label(executor, "Toggle breakpoint")? {
+                        if !self.breakpoints.remove(&label) {
+                            self.breakpoints.insert(label);
+                        }
+                    }
+                },
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(DebugAction::Quit),
+                _ => {},
+            }
+        }
+        Ok(DebugAction::Run)
+    }
+
+    /// Commands around the current one, one line each
+    fn listing(&self, executor: &Executor, rows: usize, width: usize) -> Vec<String> {
+        let script = &executor.script;
+        let start = script.position.saturating_sub(rows / 3);
+        let end = (start + rows).min(script.commands.len());
+
+        (start..end).map(|index| {
+            let command = &script.commands[index];
+            let current = if index == script.position { "=>" } else { "  " };
+            let breakpoint = match command {
+                Command::Label { name } if self.breakpoints.contains(name) => '*',
+                _ => ' ',
+            };
+            let text = write_command(command)
+                .map(|text| text.lines().next().unwrap_or_default().to_string())
+                .unwrap_or_else(|_| format!("{:?}", command));
+            truncate(&format!("{}{}{:>5} {}", current, breakpoint, index, text), width)
+        }).collect()
+    }
+
+    /// Let the author choose a label, typing to filter the list
+    fn pick_label(&self, executor: &mut Executor, title: &str) -> ScriptResult<Option<String>> {
+        let mut labels: Vec<(String, usize)> = executor.script.labels.iter()
+            .map(|(label, &index)| (label.clone(), executor.script.span(index).map_or(0, |span| span.line)))
+            .collect();
+        labels.sort_by(|a, b| (a.1, &a.0).cmp(&(b.1, &b.0)));
+
+        let mut filter = String::new();
+        let mut selected = 0;
+        loop {
+            let needle = filter.to_lowercase();
+            let matching: Vec<&(String, usize)> = labels.iter()
+                .filter(|(label, _)| label.to_lowercase().contains(&needle))
+                .collect();
+            selected = selected.min(matching.len().saturating_sub(1));
+
+            let backend = executor.backend.as_mut();
+            let (width, height) = backend.size();
+            let rows = (height as usize).saturating_sub(3).max(1);
+            let first = selected.saturating_sub(rows - 1);
+
+            backend.clear_screen()?;
+            write!(backend, "{} (type to filter, Enter to choose, Esc to cancel): {}", title, filter)?;
+            for (row, (label, line)) in matching.iter().skip(first).take(rows).enumerate() {
+                let marker = if first + row == selected { '>' } else { ' ' };
+                let breakpoint = if self.breakpoints.contains(label) { " [break]" } else { "" };
+                backend.queue(MoveTo(0, row as u16 + 2))?;
+                write!(backend, "{}", truncate(&format!("{} {} (line {}){}", marker, label, line, breakpoint), width as usize))?;
+            }
+            backend.flush()?;
+
+            let Event::Key(KeyEvent { code, .. }) = backend.read_event()? else { continue };
+            match code {
+                KeyCode::Up => selected = selected.saturating_sub(1),
+                KeyCode::Down => selected += 1,
+                KeyCode::Enter => return Ok(matching.get(selected).map(|(label, _)| label.clone())),
+                KeyCode::Esc => return Ok(None),
+                KeyCode::Backspace => {
+                    filter.pop();
+                },
+                KeyCode::Char(c) => filter.push(c),
+                _ => {},
+            }
+        }
+    }
+
+    fn draw(&self, executor: &mut Executor) -> ScriptResult<()> {
+        let (width, height) = executor.backend.size();
+        let listing_width = (width as usize).saturating_sub(PANEL_WIDTH + 1);
+        let listing = self.listing(executor, (height as usize).saturating_sub(2), listing_width);
+        let panel = panel_lines(executor, self);
+
+        let backend = executor.backend.as_mut();
+        backend.clear_screen()?;
+        for (row, line) in listing.iter().enumerate() {
+            backend.queue(MoveTo(0, row as u16))?;
+            write!(backend, "{}", line)?;
+        }
+        for (row, line) in panel.iter().enumerate() {
+            backend.queue(MoveTo(listing_width as u16 + 1, row as u16))?;
+            write!(backend, "{}", truncate(line, PANEL_WIDTH))?;
+        }
+        backend.queue(MoveTo(0, height.saturating_sub(1)))?;
+        write!(backend, "{}", truncate(HELP, width as usize))?;
+        backend.flush()?;
+        Ok(())
+    }
+}
+
+/// Executor state shown beside the listing
+fn panel_lines(executor: &Executor, debugger: &Debugger) -> Vec<String> {
+    let script = &executor.script;
+    let source = script.span(script.position).map_or_else(|| "-".to_string(), |span| {
+        let file = Path::new(&span.file).file_name().map_or(span.file.clone(), |f| f.to_string_lossy().into_owned());
+        format!("{}:{}", file, span.line)
+    });
+    let threshold = match executor.next_error_percentage {
+        Some(percentage) => format!("{:.1}% (next only)", percentage),
+        None => format!("{:.1}%", executor.error_percentage),
+    };
+    let failure_label = match (&executor.next_failure_label, &executor.failure_label) {
+        (Some(Some(label)), _) => format!("{} (next only)", label),
+        (Some(None), _) => "- (next only)".to_string(),
+        (None, Some(label)) => label.clone(),
+        (None, None) => "-".to_string(),
+    };
+    let query = match executor.last_query_response {
+        Some(true) => "yes",
+        Some(false) => "no",
+        None => "-",
+    };
+
+    vec![
+        format!("Command  {} / {}", script.position, script.commands.len()),
+        format!("Source   {}", source),
+        format!("E:       {}", threshold),
+        format!("F:       {}", failure_label),
+        format!("Query    {}", query),
+        format!("Calls    {}", executor.call_stack.len()),
+        format!("Breaks   {}", debugger.breakpoints.len()),
+        format!("Mode     {}", if debugger.stepping { "stepping" } else { "running" }),
+    ]
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::Script;
+    use crate::terminal::MemoryBackend;
+    use std::io::Write as _;
+    use tempfile::NamedTempFile;
+
+    fn executor(source: &str, keys: &[KeyCode]) -> (Executor, NamedTempFile) {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(source.as_bytes()).unwrap();
+        let script = Script::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        let mut backend = MemoryBackend::with_size(100, 20);
+        for &key in keys {
+            backend.push_key(key);
+        }
+        (Executor::with_backend(script, Box::new(backend)), temp_file)
+    }
+
+    #[test]
+    fn test_panel_shows_executor_state() {
+        let (mut executor, _file) = executor("E:5%\nF:AGAIN*\n*:AGAIN\nX:\n", &[]);
+        executor.execute_next().unwrap();
+        executor.execute_next().unwrap();
+
+        let lines = panel_lines(&executor, &Debugger::new(["AGAIN".to_string()], true));
+
+        assert_eq!(lines[0], "Command  2 / 4");
+        assert!(lines[1].ends_with(":3"));
+        assert_eq!(lines[2], "E:       5.0% (next only)");
+        assert_eq!(lines[3], "F:       AGAIN");
+        assert_eq!(lines[6], "Breaks   1");
+    }
+
+    #[test]
+    fn test_skip_jump_and_quit() {
+        let keys = [
+            KeyCode::Char('k'),
+            KeyCode::Char('g'), KeyCode::Char('e'), KeyCode::Char('n'), KeyCode::Enter,
+            KeyCode::Enter,
+            KeyCode::Char('q'),
+        ];
+        let (mut executor, _file) = executor("T:one\n*:MIDDLE\nT:two\n*:END\nB:done\nX:\n", &keys);
+        let mut debugger = Debugger::new(Vec::new(), true);
+
+        // Skip T:one, jump to END through the filtered picker, then step once
+        assert_eq!(debugger.before_command(&mut executor).unwrap(), DebugAction::Run);
+        assert_eq!(executor.script.position, 3);
+        executor.execute_next().unwrap();
+
+        assert_eq!(debugger.before_command(&mut executor).unwrap(), DebugAction::Quit);
+        assert_eq!(executor.script.position, 4);
+    }
+
+    #[test]
+    fn test_continue_runs_to_breakpoint() {
+        let (mut executor, _file) = executor("*:START\n*:STOP\nX:\n", &[KeyCode::Char('c')]);
+        let mut debugger = Debugger::new(["STOP".to_string()], true);
+
+        assert_eq!(debugger.before_command(&mut executor).unwrap(), DebugAction::Run);
+        assert!(!debugger.stepping);
+        executor.execute_next().unwrap();
+
+        // At STOP the debugger pauses again and runs out of keys
+        assert!(debugger.before_command(&mut executor).is_err());
+        assert!(debugger.stepping);
+        assert_eq!(executor.script.position, 1);
+    }
+}
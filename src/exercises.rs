@@ -4,108 +4,221 @@
 //! This replicates the functionality from the C implementation's do_tutorial, 
 //! do_drill, and do_speedtest functions.
 
-use std::time::{Duration, Instant};
+use std::collections::HashMap;
+use std::time::Duration;
 use crossterm::{
-    cursor, execute, queue,
-    style::{Color, Print, ResetColor, SetForegroundColor},
-    event::{read, Event, KeyCode, KeyEvent, KeyModifiers},
+    cursor, QueueableCommand,
+    style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor},
+    event::{Event, KeyCode, KeyEvent, KeyModifiers},
+    terminal::{Clear, ClearType},
 };
-use std::io::{stdout, Write};
-use crate::performance::{PerformanceTracker, ExerciseResult};
+use serde::{Deserialize, Serialize};
+use crate::performance::ExerciseResult;
+use crate::terminal::{Backend, write_centered, write_line, write_wrapped_text};
+use crate::typing::{CharStatus, KeyEffect, TypingOptions, TypingSession};
 
-/// Helper function to center text in terminal
-fn center_text(text: &str) -> String {
-    // Try to get terminal size, fall back to reasonable defaults
-    let width = match crossterm::terminal::size() {
-        Ok((w, _)) => w as usize,
-        Err(_) => {
-            // If we can't get terminal size, try environment variables or use 80
-            std::env::var("COLUMNS")
-                .ok()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(80)
+/// Exercise execution results
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExerciseOutcome {
+    /// Exercise completed successfully
+    Completed(ExerciseResult),
+    /// User quit the exercise
+    Quit,
+    /// Exercise failed (too many errors), with what was typed
+    Failed(ExerciseResult),
+    /// User requested retry
+    Retry,
+    /// User pressed a function key bound to a label
+    Jump(String),
+}
+
+/// First screen row of the text area in drills
+const DRILL_TEXT_TOP: u16 = 5;
+
+/// First screen row of the text area in speed tests, below the live stats
+const SPEED_TEST_TEXT_TOP: u16 = 7;
+
+/// Columns between tab stops when drawing exercise text
+const TAB_WIDTH: usize = 8;
+
+/// Normalize line endings and drop trailing newlines, so Enter is only
+/// expected between lines
+fn normalize_exercise_text(text: &str) -> String {
+    text.replace("\r\n", "\n").trim_end_matches('\n').to_string()
+}
+
+/// Screen position (line, column) of every character of an exercise text
+///
+/// A newline sits just past the end of its line, and tabs advance to the
+/// next tab stop. With a `wrap` width, a word that would run past it
+/// starts a new line instead, as in a word processor.
+fn layout_positions(target_chars: &[char], wrap: Option<usize>) -> Vec<(usize, usize)> {
+    let mut positions = Vec::with_capacity(target_chars.len());
+    let (mut line, mut column) = (0, 0);
+    
+    for (i, &ch) in target_chars.iter().enumerate() {
+        if let Some(width) = wrap {
+            let starts_word = i > 0 && target_chars[i - 1] == ' ' && !ch.is_whitespace();
+            if starts_word && column > 0 {
+                let word = target_chars[i..].iter().take_while(|c| !c.is_whitespace()).count();
+                if column + word > width {
+                    line += 1;
+                    column = 0;
+                }
+            }
+        }
+        
+        positions.push((line, column));
+        match ch {
+            '\n' => {
+                line += 1;
+                column = 0;
+            },
+            '\t' => column = (column / TAB_WIDTH + 1) * TAB_WIDTH,
+            _ => column += 1,
         }
-    };
+    }
+    
+    positions
+}
+
+/// Exercise text split into display lines, with tabs expanded
+fn display_lines(target_chars: &[char], wrap: Option<usize>) -> Vec<String> {
+    let mut lines = vec![String::new()];
     
-    let text_len = text.chars().count(); // Use char count for proper Unicode handling
-    if text_len >= width {
-        return text.to_string(); // Don't try to center if text is too long
+    for (&ch, &(line, column)) in target_chars.iter().zip(&layout_positions(target_chars, wrap)) {
+        while lines.len() <= line {
+            lines.push(String::new());
+        }
+        let current = &mut lines[line];
+        let width = current.chars().count();
+        match ch {
+            '\n' => lines.push(String::new()),
+            '\t' => {
+                let stop = (width / TAB_WIDTH + 1) * TAB_WIDTH;
+                current.push_str(&" ".repeat(stop - width));
+            },
+            _ => {
+                current.push_str(&" ".repeat(column.saturating_sub(width)));
+                current.push(ch);
+            },
+        }
     }
     
-    let padding = (width - text_len) / 2;
-    format!("{}{}", " ".repeat(padding), text)
+    lines
 }
 
-/// Get appropriate line width for text content (smaller than terminal width)
-fn get_content_width() -> usize {
-    // Use a more conservative width to ensure proper wrapping
-    50 // Fixed 50 characters for consistent, readable line lengths
+/// Width to wrap exercise text at on this screen, in word-processor mode
+fn wrap_width(backend: &dyn Backend, options: TypingOptions) -> Option<usize> {
+    options.word_processor.then(|| (backend.size().0 as usize).saturating_sub(1).max(1))
 }
 
-/// Print text with proper word wrapping and cursor positioning
-fn print_wrapped_text(text: &str) {
-    const LINE_WIDTH: usize = 50;
+/// Draw the exercise text line by line, each target line followed by the
+/// line the user has typed so far
+///
+/// Only as many lines as fit on the screen are shown; the window pages
+/// forward as the user reaches later lines.
+fn draw_text_area(
+    backend: &mut dyn Backend,
+    top: u16,
+    session: &TypingSession,
+) -> std::io::Result<()> {
+    let target_chars = session.target();
+    let typed = session.typed();
+    let wrap = wrap_width(backend, session.options());
+    let show_errors = session.options().show_errors;
+    let lines = display_lines(target_chars, wrap);
+    let positions = layout_positions(target_chars, wrap);
     
-    // Split text into words and wrap them
-    let words: Vec<&str> = text.split_whitespace().collect();
-    let mut current_line = String::new();
+    let height = backend.size().1;
+    let visible = (height.saturating_sub(top + 2) / 2).max(1) as usize;
+    let current_line = positions.get(typed.len())
+        .map_or(lines.len() - 1, |&(line, _)| line);
+    let first = current_line / visible * visible;
+    let last = (first + visible).min(lines.len());
+    
+    for (row, line) in lines[first..last].iter().enumerate() {
+        let y = top + (row * 2) as u16;
+        backend.queue(cursor::MoveTo(0, y))?
+            .queue(Clear(ClearType::CurrentLine))?
+            .queue(Print(line))?
+            .queue(cursor::MoveTo(0, y + 1))?
+            .queue(Clear(ClearType::CurrentLine))?;
+    }
     
-    for word in words {
-        // If adding this word would exceed the line width, print current line and start new one
-        if !current_line.is_empty() && current_line.len() + 1 + word.len() > LINE_WIDTH {
-            // Force cursor to column 1 and print the line
-            print!("\x1B[1G{}\n", current_line);
-            current_line.clear();
+    // Display typed characters under their targets with error highlighting
+    for (i, &(line, column)) in positions.iter().enumerate().take(typed.len()) {
+        if line < first || line >= last {
+            continue;
         }
         
-        // Add word to current line
-        if !current_line.is_empty() {
-            current_line.push(' ');
-        }
-        current_line.push_str(word);
+        let (ch, correct) = match session.status(i) {
+            CharStatus::Correct if target_chars[i] == '\n' || target_chars[i] == '\t' => continue,
+            CharStatus::Correct => (target_chars[i], true),
+            CharStatus::Wrong(ch) => (ch, false),
+            // Left out and skipped by sync-ahead
+            CharStatus::Missed => ('_', false),
+            CharStatus::Pending => continue,
+        };
+        let color = if correct { Color::Green } else { Color::Red };
+        backend.queue(cursor::MoveTo(column as u16, top + ((line - first) * 2 + 1) as u16))?;
+        draw_typed_char(backend, ch, color, !correct && show_errors)?;
     }
     
-    // Print any remaining text
-    if !current_line.is_empty() {
-        print!("\x1B[1G{}\n", current_line);
+    // Show cursor position, or the wrong key stop-on-error is holding there
+    if let Some(&(line, column)) = positions.get(typed.len()) {
+        backend.queue(cursor::MoveTo(column as u16, top + ((line - first) * 2 + 1) as u16))?;
+        match session.rejected() {
+            Some(ch) => draw_typed_char(backend, ch, Color::Red, show_errors)?,
+            None => draw_typed_char(backend, '|', Color::Yellow, false)?,
+        }
     }
+    
+    backend.queue(ResetColor)?;
+    Ok(())
 }
 
-/// Exercise execution results
-#[derive(Debug, Clone, PartialEq)]
-pub enum ExerciseOutcome {
-    /// Exercise completed successfully
-    Completed(ExerciseResult),
-    /// User quit the exercise
-    Quit,
-    /// Exercise failed (too many errors)
-    Failed,
-    /// User requested retry
-    Retry,
+/// Draw one character of the typed line, control characters as `^`
+fn draw_typed_char(backend: &mut dyn Backend, ch: char, color: Color, reverse: bool) -> std::io::Result<()> {
+    let shown = if ch.is_control() { '^' } else { ch };
+    backend.queue(SetForegroundColor(color))?;
+    if reverse {
+        backend.queue(SetAttribute(Attribute::Reverse))?
+            .queue(Print(shown))?
+            .queue(SetAttribute(Attribute::NoReverse))?;
+    } else {
+        backend.queue(Print(shown))?;
+    }
+    Ok(())
 }
 
 /// Tutorial exercise - display-only, no user input required
 #[derive(Debug, Clone)]
 pub struct TutorialExercise {
     pub text: String,
+    pub key_bindings: HashMap<u8, String>,
 }
 
 impl TutorialExercise {
     pub fn new(text: String) -> Self {
-        Self { text }
+        Self { text, key_bindings: HashMap::new() }
+    }
+    
+    /// Let the given function keys jump to their bound labels
+    pub fn with_key_bindings(mut self, key_bindings: &HashMap<u8, String>) -> Self {
+        self.key_bindings = key_bindings.clone();
+        self
     }
     
     /// Execute tutorial - just display text and wait for user
-    pub fn execute(&self) -> Result<ExerciseOutcome, Box<dyn std::error::Error>> {
-        let mut stdout = stdout();
-        
+    pub fn execute(&self, backend: &mut dyn Backend) -> Result<ExerciseOutcome, Box<dyn std::error::Error>> {
         // Clear screen and position cursor at top-left
-        print!("\x1B[2J\x1B[H");
-        stdout.flush()?;
+        backend.clear_screen()?;
         
-        println!();
-        println!("{}", center_text("=== TUTORIAL ==="));
-        println!();
+        write_line(backend, "")?;
+        write_centered(backend, "=== TUTORIAL ===")?;
+        write_line(backend, "")?;
         
         // Display the tutorial text (truncated to prevent excessive output)
         const MAX_TUTORIAL_DISPLAY: usize = 2000;
@@ -117,22 +230,27 @@ impl TutorialExercise {
         
         // Print text with proper wrapping, each line at left margin
         let clean_text = display_text.replace('\t', " ");
-        print_wrapped_text(&clean_text);
+        write_wrapped_text(backend, &clean_text)?;
         
-        println!();
-        println!();
-        println!("Press SPACE to continue, ESC to quit...");
-        stdout.flush()?;
+        write_line(backend, "")?;
+        write_line(backend, "")?;
+        write_line(backend, "Press SPACE to continue, ESC to quit...")?;
+        backend.flush()?;
         
         // Wait for user input
         loop {
-            match read()? {
+            match backend.read_event()? {
                 Event::Key(KeyEvent { code: KeyCode::Char(' '), .. }) => {
                     return Ok(ExerciseOutcome::Completed(ExerciseResult::default()));
                 },
                 Event::Key(KeyEvent { code: KeyCode::Esc, .. }) => {
                     return Ok(ExerciseOutcome::Quit);
                 },
+                Event::Key(KeyEvent { code: KeyCode::F(n), .. }) => {
+                    if let Some(label) = self.key_bindings.get(&n) {
+                        return Ok(ExerciseOutcome::Jump(label.clone()));
+                    }
+                },
                 _ => continue,
             }
         }
@@ -145,53 +263,50 @@ pub struct DrillExercise {
     pub text: String,
     pub practice_only: bool,
     pub max_error_rate: f32,
+    pub key_bindings: HashMap<u8, String>,
+    pub options: TypingOptions,
 }
 
 impl DrillExercise {
     pub fn new(text: String, practice_only: bool, max_error_rate: f32) -> Self {
         Self { 
-            text, 
+            text: normalize_exercise_text(&text), 
             practice_only, 
-            max_error_rate: if max_error_rate <= 0.0 { 100.0 } else { max_error_rate }
+            max_error_rate,
+            key_bindings: HashMap::new(),
+            options: TypingOptions::default(),
         }
     }
     
+    /// Let the given function keys jump to their bound labels
+    pub fn with_key_bindings(mut self, key_bindings: &HashMap<u8, String>) -> Self {
+        self.key_bindings = key_bindings.clone();
+        self
+    }
+    
+    /// Type under the given options
+    pub fn with_options(mut self, options: TypingOptions) -> Self {
+        self.options = options;
+        self
+    }
+    
     /// Execute drill exercise with real-time feedback
-    pub fn execute(&self) -> Result<ExerciseOutcome, Box<dyn std::error::Error>> {
-        let mut stdout = stdout();
-        let mut tracker = PerformanceTracker::new();
-        let target_chars: Vec<char> = self.text.chars().collect();
-        let mut position = 0;
-        let mut typed_text = String::new();
-        
-        // Clear screen with direct ANSI codes
-        print!("\x1B[2J\x1B[1;1H");
-        
-        println!();
-        println!("{}", center_text(&format!("=== {} ===", 
-            if self.practice_only { "DRILL PRACTICE" } else { "DRILL" })));
-        println!();
-        print!("\x1B[1GType the following text. Press ESC to quit, Ctrl+R to retry.\n");
-        println!();
-        
-        // Display target text (truncated to prevent excessive output)
-        const MAX_TARGET_DISPLAY: usize = 500;
-        let display_text = if self.text.len() > MAX_TARGET_DISPLAY {
-            format!("{}...", &self.text[..MAX_TARGET_DISPLAY])
-        } else {
-            self.text.clone()
-        };
-        
-        print!("\x1B[1GTarget:\n");
-        print!("\x1B[1G{}\n", display_text);
-        println!();
-        print!("\x1B[1GYour typing:\n");
-        stdout.flush()?;
-        
-        let start_time = Instant::now();
-        
-        loop {
-            match read()? {
+    pub fn execute(&self, backend: &mut dyn Backend) -> Result<ExerciseOutcome, Box<dyn std::error::Error>> {
+        backend.clear_screen()?;
+        
+        write_line(backend, "")?;
+        write_centered(backend, &format!("=== {} ===", 
+            if self.practice_only { "DRILL PRACTICE" } else { "DRILL" }))?;
+        write_line(backend, "")?;
+        write_line(backend, "Type the following text. Press ESC to quit, Ctrl+R to retry.")?;
+        write_line(backend, "")?;
+        
+        let mut session = TypingSession::new(&self.text, backend.now()).with_options(self.options);
+        draw_text_area(backend, DRILL_TEXT_TOP, &session)?;
+        backend.flush()?;
+        
+        while !session.is_complete() {
+            match backend.read_event()? {
                 Event::Key(KeyEvent { code: KeyCode::Esc, .. }) => {
                     return Ok(ExerciseOutcome::Quit);
                 },
@@ -202,117 +317,56 @@ impl DrillExercise {
                 }) => {
                     return Ok(ExerciseOutcome::Retry);
                 },
-                Event::Key(KeyEvent { code: KeyCode::Char(ch), .. }) => {
-                    if position < target_chars.len() {
-                        let expected = target_chars[position];
-                        typed_text.push(ch);
-                        
-                        if ch == expected {
-                            tracker.record_correct_char();
-                        } else {
-                            tracker.record_error();
-                        }
-                        
-                        position += 1;
-                        
-                        // Display progress after each character
-                        self.display_progress(&mut stdout, &typed_text, &target_chars, position)?;
-                        
-                        // Check if exercise is complete
-                        if position >= target_chars.len() {
-                            break;
-                        }
-                        
-                        // Check error rate if not practice mode
-                        if !self.practice_only && tracker.error_rate() > self.max_error_rate {
-                            println!("\nToo many errors! Try again.");
-                            return Ok(ExerciseOutcome::Failed);
-                        }
+                Event::Key(KeyEvent { code: KeyCode::F(n), .. }) => {
+                    if let Some(label) = self.key_bindings.get(&n) {
+                        return Ok(ExerciseOutcome::Jump(label.clone()));
                     }
                 },
-                Event::Key(KeyEvent { code: KeyCode::Backspace, .. }) => {
-                    if position > 0 && !typed_text.is_empty() {
-                        position -= 1;
-                        typed_text.pop();
-                        tracker.record_backspace();
-                        self.display_progress(&mut stdout, &typed_text, &target_chars, position)?;
+                Event::Key(key) => {
+                    if session.handle_key(&key, backend.now()) != KeyEffect::Ignored {
+                        // Display progress after each character
+                        draw_text_area(backend, DRILL_TEXT_TOP, &session)?;
+                        backend.flush()?;
                     }
                 },
                 _ => continue,
             }
         }
         
-        let duration = start_time.elapsed();
-        tracker.set_duration(duration);
+        let result = session.finish(backend.now());
         
-        let result = ExerciseResult {
-            total_chars: target_chars.len(),
-            correct_chars: tracker.correct_chars(),
-            errors: tracker.errors(),
-            duration,
-            wpm: tracker.words_per_minute(),
-            error_rate: tracker.error_rate(),
-        };
+        // The error limit is judged on the whole drill, not partway through
+        let failed = !self.practice_only && result.error_rate > self.max_error_rate;
         
         // Display final results
-        self.display_results(&result)?;
+        self.display_results(backend, &result, failed)?;
         
+        if failed {
+            return Ok(ExerciseOutcome::Failed(result));
+        }
         Ok(ExerciseOutcome::Completed(result))
     }
     
-    fn display_progress(
-        &self, 
-        stdout: &mut std::io::Stdout,
-        typed_text: &str,
-        target_chars: &[char],
-        position: usize
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        // Move cursor to typing area (line after "Your typing:")
-        execute!(stdout, cursor::MoveTo(0, 9))?;
-        
-        // Limit display to prevent excessive output that could cause RangeError
-        const MAX_DISPLAY_CHARS: usize = 1000;
-        let display_limit = typed_text.len().min(MAX_DISPLAY_CHARS);
-        
-        // Display typed characters with error highlighting
-        for (i, ch) in typed_text.chars().take(display_limit).enumerate() {
-            if i < target_chars.len() {
-                if ch == target_chars[i] {
-                    queue!(stdout, SetForegroundColor(Color::Green), Print(ch))?;
-                } else {
-                    queue!(stdout, SetForegroundColor(Color::Red), Print(ch))?;
-                }
-            }
-        }
-        
-        // Show cursor position
-        if position < target_chars.len() {
-            queue!(stdout, SetForegroundColor(Color::Yellow), Print('|'))?;
+    fn display_results(&self, backend: &mut dyn Backend, result: &ExerciseResult, failed: bool) -> Result<(), Box<dyn std::error::Error>> {
+        backend.clear_screen()?;
+        
+        write_line(backend, "")?;
+        write_centered(backend, "=== RESULTS ===")?;
+        write_line(backend, "")?;
+        write_line(backend, &format!("Characters typed: {}", result.total_chars))?;
+        write_line(backend, &format!("Correct: {}", result.correct_chars))?;
+        write_line(backend, &format!("Errors: {}", result.errors))?;
+        write_line(backend, &format!("Accuracy: {:.1}%", 100.0 - result.error_rate))?;
+        write_line(backend, &format!("Speed: {:.1} WPM", result.wpm))?;
+        write_line(backend, &format!("Time: {:.1}s", result.duration.as_secs_f32()))?;
+        write_line(backend, "")?;
+        if failed {
+            write_line(backend, &format!("Too many errors (limit {:.1}%)! Try again.", self.max_error_rate))?;
         }
+        write_line(backend, "Press any key to continue...")?;
+        backend.flush()?;
         
-        queue!(stdout, ResetColor)?;
-        stdout.flush()?;
-        
-        Ok(())
-    }
-    
-    fn display_results(&self, result: &ExerciseResult) -> Result<(), Box<dyn std::error::Error>> {
-        // Clear screen with direct ANSI codes
-        print!("\x1B[2J\x1B[1;1H");
-        
-        println!();
-        println!("{}", center_text("=== RESULTS ==="));
-        println!();
-        print!("\x1B[1GCharacters typed: {}\n", result.total_chars);
-        print!("\x1B[1GCorrect: {}\n", result.correct_chars);
-        print!("\x1B[1GErrors: {}\n", result.errors);
-        print!("\x1B[1GAccuracy: {:.1}%\n", 100.0 - result.error_rate);
-        print!("\x1B[1GSpeed: {:.1} WPM\n", result.wpm);
-        print!("\x1B[1GTime: {:.1}s\n", result.duration.as_secs_f32());
-        println!();
-        print!("\x1B[1GPress any key to continue...\n");
-        
-        read()?;
+        backend.read_event()?;
         Ok(())
     }
 }
@@ -323,188 +377,150 @@ pub struct SpeedTestExercise {
     pub text: String,
     pub practice_only: bool,
     pub time_limit: Option<Duration>,
+    pub key_bindings: HashMap<u8, String>,
+    pub options: TypingOptions,
 }
 
 impl SpeedTestExercise {
     pub fn new(text: String, practice_only: bool, time_limit: Option<Duration>) -> Self {
         Self { 
-            text, 
+            text: normalize_exercise_text(&text), 
             practice_only,
-            time_limit 
+            time_limit,
+            key_bindings: HashMap::new(),
+            options: TypingOptions::default(),
         }
     }
     
+    /// Let the given function keys jump to their bound labels
+    pub fn with_key_bindings(mut self, key_bindings: &HashMap<u8, String>) -> Self {
+        self.key_bindings = key_bindings.clone();
+        self
+    }
+    
+    /// Type under the given options
+    pub fn with_options(mut self, options: TypingOptions) -> Self {
+        self.options = options;
+        self
+    }
+    
     /// Execute speed test with timer
-    pub fn execute(&self) -> Result<ExerciseOutcome, Box<dyn std::error::Error>> {
-        let mut stdout = stdout();
-        let mut tracker = PerformanceTracker::new();
+    pub fn execute(&self, backend: &mut dyn Backend) -> Result<ExerciseOutcome, Box<dyn std::error::Error>> {
         let target_chars: Vec<char> = self.text.chars().collect();
-        let mut position = 0;
-        let mut typed_text = String::new();
+        let title = if self.practice_only { "SPEED TEST PRACTICE" } else { "SPEED TEST" };
         
-        // Clear screen with direct ANSI codes
-        print!("\x1B[2J\x1B[1;1H");
+        backend.clear_screen()?;
         
-        println!();
-        println!("{}", center_text(&format!("=== {} ===", 
-            if self.practice_only { "SPEED TEST PRACTICE" } else { "SPEED TEST" })));
+        write_line(backend, "")?;
+        write_centered(backend, &format!("=== {} ===", title))?;
         
         if let Some(time_limit) = self.time_limit {
-            println!("Time limit: {} seconds", time_limit.as_secs());
+            write_line(backend, &format!("Time limit: {} seconds", time_limit.as_secs()))?;
         }
-        println!();
-        print!("\x1B[1GType as fast and accurately as possible. Press ESC to quit.\n");
-        println!();
-        
-        // Display target text (truncated to prevent excessive output)
-        const MAX_TARGET_DISPLAY: usize = 500;
-        let display_text = if self.text.len() > MAX_TARGET_DISPLAY {
-            format!("{}...", &self.text[..MAX_TARGET_DISPLAY])
-        } else {
-            self.text.clone()
-        };
-        
-        print!("\x1B[1GText to type:\n");
-        print!("\x1B[1G{}\n", display_text);
-        println!();
-        print!("\x1B[1GPress any key to start...\n");
-        stdout.flush()?;
+        write_line(backend, "")?;
+        write_line(backend, "Type as fast and accurately as possible. Press ESC to quit.")?;
+        write_line(backend, "")?;
+        write_line(backend, "Text to type:")?;
+        for line in display_lines(&target_chars, wrap_width(backend, self.options)) {
+            write_line(backend, &line)?;
+        }
+        write_line(backend, "")?;
+        write_line(backend, "Press any key to start...")?;
+        backend.flush()?;
         
         // Wait for start signal
-        read()?;
+        if let Event::Key(KeyEvent { code: KeyCode::F(n), .. }) = backend.read_event()? {
+            if let Some(label) = self.key_bindings.get(&n) {
+                return Ok(ExerciseOutcome::Jump(label.clone()));
+            }
+        }
         
-        let start_time = Instant::now();
+        let mut session = TypingSession::new(&self.text, backend.now()).with_options(self.options);
         
-        loop {
+        backend.clear_screen()?;
+        write_line(backend, "")?;
+        write_centered(backend, &format!("=== {} ===", title))?;
+        self.display_speed_progress(backend, &session)?;
+        
+        while !session.is_complete() {
             // Check time limit
             if let Some(time_limit) = self.time_limit {
-                if start_time.elapsed() >= time_limit {
-                    println!("\nTime's up!");
+                if session.elapsed(backend.now()) >= time_limit {
+                    let height = backend.size().1;
+                    backend.queue(cursor::MoveTo(0, height.saturating_sub(2)))?;
+                    write_line(backend, "Time's up!")?;
                     break;
                 }
             }
             
-            match read()? {
+            match backend.read_event()? {
                 Event::Key(KeyEvent { code: KeyCode::Esc, .. }) => {
                     return Ok(ExerciseOutcome::Quit);
                 },
-                Event::Key(KeyEvent { code: KeyCode::Char(ch), .. }) => {
-                    if position < target_chars.len() {
-                        let expected = target_chars[position];
-                        typed_text.push(ch);
-                        
-                        if ch == expected {
-                            tracker.record_correct_char();
-                        } else {
-                            tracker.record_error();
-                        }
-                        
-                        position += 1;
-                        
-                        // Display progress after each character
-                        self.display_speed_progress(&mut stdout, &typed_text, &target_chars, position, start_time)?;
-                        
-                        // Check if test is complete
-                        if position >= target_chars.len() {
-                            break;
-                        }
+                Event::Key(KeyEvent { code: KeyCode::F(n), .. }) => {
+                    if let Some(label) = self.key_bindings.get(&n) {
+                        return Ok(ExerciseOutcome::Jump(label.clone()));
                     }
                 },
-                Event::Key(KeyEvent { code: KeyCode::Backspace, .. }) => {
-                    if position > 0 && !typed_text.is_empty() {
-                        position -= 1;  
-                        typed_text.pop();
-                        tracker.record_backspace();
-                        self.display_speed_progress(&mut stdout, &typed_text, &target_chars, position, start_time)?;
+                Event::Key(key) => {
+                    if session.handle_key(&key, backend.now()) != KeyEffect::Ignored {
+                        // Display progress after each character
+                        self.display_speed_progress(backend, &session)?;
                     }
                 },
                 _ => continue,
             }
         }
         
-        let duration = start_time.elapsed();
-        tracker.set_duration(duration);
-        
-        let result = ExerciseResult {
-            total_chars: position,
-            correct_chars: tracker.correct_chars(),
-            errors: tracker.errors(),
-            duration,
-            wpm: tracker.words_per_minute(),
-            error_rate: tracker.error_rate(),
-        };
+        let result = session.finish(backend.now());
         
         // Display final results
-        self.display_speed_results(&result)?;
+        self.display_speed_results(backend, &result)?;
         
         Ok(ExerciseOutcome::Completed(result))
     }
     
     fn display_speed_progress(
         &self,
-        stdout: &mut std::io::Stdout,
-        typed_text: &str,
-        target_chars: &[char],
-        position: usize,
-        start_time: Instant
+        backend: &mut dyn Backend,
+        session: &TypingSession,
     ) -> Result<(), Box<dyn std::error::Error>> {
         // Move to progress area
-        execute!(stdout, cursor::MoveTo(0, 5))?;
+        backend.queue(cursor::MoveTo(0, 3))?;
         
         // Display timer
-        let elapsed = start_time.elapsed().as_secs_f32();
-        println!("Time: {:.1}s", elapsed);
+        let elapsed = session.elapsed(backend.now()).as_secs_f32();
+        write_line(backend, &format!("Time: {:.1}s", elapsed))?;
         
         // Calculate real-time WPM
-        if elapsed > 0.0 {
-            let chars_per_minute = (position as f32 / elapsed) * 60.0;
-            let wpm = chars_per_minute / 5.0; // Standard: 5 chars = 1 word
-            println!("Current WPM: {:.1}", wpm);
-        }
-        
-        println!("Progress: {}/{} characters\n", position, target_chars.len());
-        
-        // Limit display to prevent excessive output that could cause RangeError
-        const MAX_DISPLAY_CHARS: usize = 1000;
-        let display_limit = typed_text.len().min(MAX_DISPLAY_CHARS);
-        
-        // Display typed text with highlighting
-        print!("Typed: ");
-        for (i, ch) in typed_text.chars().take(display_limit).enumerate() {
-            if i < target_chars.len() {
-                if ch == target_chars[i] {
-                    queue!(stdout, SetForegroundColor(Color::Green), Print(ch))?;
-                } else {
-                    queue!(stdout, SetForegroundColor(Color::Red), Print(ch))?;
-                }
-            }
-        }
-        
-        // Add indication if text was truncated
-        if typed_text.len() > MAX_DISPLAY_CHARS {
-            queue!(stdout, SetForegroundColor(Color::Blue), Print("..."))?;
-        }
+        let wpm = if elapsed > 0.0 {
+            let chars_per_minute = (session.position() as f32 / elapsed) * 60.0;
+            chars_per_minute / 5.0 // Standard: 5 chars = 1 word
+        } else {
+            0.0
+        };
+        write_line(backend, &format!("Current WPM: {:.1}", wpm))?;
+        write_line(backend, &format!("Progress: {}/{} characters", session.position(), session.target().len()))?;
         
-        queue!(stdout, ResetColor)?;
-        stdout.flush()?;
+        draw_text_area(backend, SPEED_TEST_TEXT_TOP, session)?;
+        backend.flush()?;
         
         Ok(())
     }
     
-    fn display_speed_results(&self, result: &ExerciseResult) -> Result<(), Box<dyn std::error::Error>> {
-        // Clear screen with direct ANSI codes
-        print!("\x1B[2J\x1B[1;1H");
-        
-        println!();
-        println!("{}", center_text("=== SPEED TEST RESULTS ==="));
-        println!();
-        print!("\x1B[1GCharacters typed: {}\n", result.total_chars);
-        print!("\x1B[1GCorrect characters: {}\n", result.correct_chars);
-        print!("\x1B[1GErrors: {}\n", result.errors);
-        print!("\x1B[1GAccuracy: {:.1}%\n", 100.0 - result.error_rate);
-        print!("\x1B[1GSpeed: {:.1} WPM\n", result.wpm);
-        print!("\x1B[1GTime: {:.1} seconds\n", result.duration.as_secs_f32());
-        println!();
+    fn display_speed_results(&self, backend: &mut dyn Backend, result: &ExerciseResult) -> Result<(), Box<dyn std::error::Error>> {
+        backend.clear_screen()?;
+        
+        write_line(backend, "")?;
+        write_centered(backend, "=== SPEED TEST RESULTS ===")?;
+        write_line(backend, "")?;
+        write_line(backend, &format!("Characters typed: {}", result.total_chars))?;
+        write_line(backend, &format!("Correct characters: {}", result.correct_chars))?;
+        write_line(backend, &format!("Errors: {}", result.errors))?;
+        write_line(backend, &format!("Accuracy: {:.1}%", 100.0 - result.error_rate))?;
+        write_line(backend, &format!("Speed: {:.1} WPM", result.wpm))?;
+        write_line(backend, &format!("Time: {:.1} seconds", result.duration.as_secs_f32()))?;
+        write_line(backend, "")?;
         
         // Grade the performance
         let grade = if result.wpm >= 40.0 && result.error_rate <= 5.0 {
@@ -514,11 +530,161 @@ impl SpeedTestExercise {
         } else {
             "Keep practicing!"
         };
-        print!("\x1B[1G{}\n", grade);
-        println!();
-        print!("\x1B[1GPress any key to continue...\n");
+        write_line(backend, grade)?;
+        write_line(backend, "")?;
+        write_line(backend, "Press any key to continue...")?;
+        backend.flush()?;
         
-        read()?;
+        backend.read_event()?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::MemoryBackend;
+    
+    #[test]
+    fn test_tutorial_headless() {
+        let mut backend = MemoryBackend::new();
+        backend.push_key(KeyCode::Char(' '));
+        
+        let exercise = TutorialExercise::new("Welcome to the lesson".to_string());
+        let outcome = exercise.execute(&mut backend).unwrap();
+        
+        assert!(matches!(outcome, ExerciseOutcome::Completed(_)));
+        assert!(backend.plain_output().contains("Welcome to the lesson"));
+    }
+    
+    #[test]
+    fn test_drill_headless() {
+        let mut backend = MemoryBackend::new();
+        backend.push_text("asdx");
+        backend.push_key(KeyCode::Enter); // dismiss results screen
+        
+        let exercise = DrillExercise::new("asdf".to_string(), true, 100.0);
+        match exercise.execute(&mut backend).unwrap() {
+            ExerciseOutcome::Completed(result) => {
+                assert_eq!(result.total_chars, 4);
+                assert_eq!(result.correct_chars, 3);
+                assert_eq!(result.errors, 1);
+            },
+            other => panic!("Expected completed drill, got {:?}", other),
+        }
+        assert_eq!(backend.pending_events(), 0);
+    }
+    
+    #[test]
+    fn test_drill_error_limit_applies_to_whole_drill() {
+        let mut backend = MemoryBackend::new();
+        backend.push_text("xbcd");
+        backend.push_key(KeyCode::Enter);
+        
+        // One error in four is 25%, only judged once the drill is done
+        let exercise = DrillExercise::new("abcd".to_string(), false, 20.0);
+        assert!(matches!(exercise.execute(&mut backend).unwrap(), ExerciseOutcome::Failed(result) if result.errors == 1));
+        assert!(backend.plain_output().contains("Too many errors (limit 20.0%)"));
+        
+        backend.push_text("xbcd");
+        backend.push_key(KeyCode::Enter);
+        let exercise = DrillExercise::new("abcd".to_string(), false, 25.0);
+        assert!(matches!(exercise.execute(&mut backend).unwrap(), ExerciseOutcome::Completed(_)));
+    }
+    
+    #[test]
+    fn test_drill_requires_enter_between_lines() {
+        let mut backend = MemoryBackend::new();
+        backend.push_text("ab");
+        backend.push_key(KeyCode::Enter);
+        backend.push_text("c");
+        backend.push_key(KeyCode::Tab);
+        backend.push_text("d");
+        backend.push_key(KeyCode::Enter); // dismiss results screen
+        
+        let exercise = DrillExercise::new("ab\nc\td\n".to_string(), false, 100.0);
+        match exercise.execute(&mut backend).unwrap() {
+            ExerciseOutcome::Completed(result) => {
+                assert_eq!(result.total_chars, 6);
+                assert_eq!(result.errors, 0);
+            },
+            other => panic!("Expected completed drill, got {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn test_drill_space_at_line_end_is_an_error() {
+        let mut backend = MemoryBackend::new();
+        backend.push_text("ab cd");
+        backend.push_key(KeyCode::Enter);
+        
+        let exercise = DrillExercise::new("ab\ncd".to_string(), true, 100.0);
+        match exercise.execute(&mut backend).unwrap() {
+            ExerciseOutcome::Completed(result) => assert_eq!(result.errors, 1),
+            other => panic!("Expected completed drill, got {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn test_layout_positions() {
+        let target: Vec<char> = "ab\n\tc".chars().collect();
+        assert_eq!(layout_positions(&target, None), vec![(0, 0), (0, 1), (0, 2), (1, 0), (1, 8)]);
+        assert_eq!(display_lines(&target, None), vec!["ab".to_string(), "        c".to_string()]);
+    }
+    
+    #[test]
+    fn test_word_processor_wraps_at_words() {
+        let target: Vec<char> = "one two three\nfour".chars().collect();
+        assert_eq!(display_lines(&target, Some(9)), vec!["one two ", "three", "four"]);
+        assert_eq!(layout_positions(&target, Some(9))[8], (1, 0));
+        assert_eq!(display_lines(&target, None), vec!["one two three", "four"]);
+    }
+    
+    #[test]
+    fn test_drill_word_processor_mode() {
+        let mut backend = MemoryBackend::new();
+        backend.push_text("ab cd");
+        backend.push_key(KeyCode::Enter);
+        
+        let exercise = DrillExercise::new("ab  \ncd".to_string(), false, 0.0)
+            .with_options(TypingOptions { word_processor: true, ..TypingOptions::default() });
+        match exercise.execute(&mut backend).unwrap() {
+            ExerciseOutcome::Completed(result) => {
+                assert_eq!(result.total_chars, 7);
+                assert_eq!(result.errors, 0);
+            },
+            other => panic!("Expected completed drill, got {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn test_drill_stop_on_error_shows_errors() {
+        let mut backend = MemoryBackend::new();
+        backend.push_text("xab");
+        backend.push_key(KeyCode::Enter);
+        
+        let options = TypingOptions { stop_on_error: true, show_errors: true, ..TypingOptions::default() };
+        let exercise = DrillExercise::new("ab".to_string(), true, 0.0).with_options(options);
+        match exercise.execute(&mut backend).unwrap() {
+            ExerciseOutcome::Completed(result) => {
+                assert_eq!(result.total_chars, 2);
+                assert_eq!(result.correct_chars, 2);
+                assert_eq!(result.errors, 1);
+            },
+            other => panic!("Expected completed drill, got {:?}", other),
+        }
+        // The held wrong key is drawn in reverse video at the cursor
+        assert!(backend.output().contains("\x1b[7mx\x1b[27m"));
+    }
+    
+    #[test]
+    fn test_speed_test_quit() {
+        let mut backend = MemoryBackend::new();
+        backend.push_key(KeyCode::Enter); // start signal
+        backend.push_text("ab");
+        backend.push_key(KeyCode::Esc);
+        
+        let exercise = SpeedTestExercise::new("abcdef".to_string(), false, None);
+        assert_eq!(exercise.execute(&mut backend).unwrap(), ExerciseOutcome::Quit);
+    }
+}
@@ -8,10 +8,22 @@ pub mod script;
 pub mod exercises;
 pub mod performance;
 pub mod menu;
+pub mod terminal;
+pub mod typing;
+pub mod runner;
+pub mod lint;
+pub mod debugger;
+pub mod coverage;
 
-pub use script::{Script, ScriptError, ScriptResult, load_text_file};
+pub use script::{Script, ScriptError, ScriptResult, SourceSpan, load_text_file};
 pub use script::commands::Command;
+pub use script::metadata::LessonMetadata;
+pub use script::search::LessonSearch;
+pub use script::state::{ExecutorState, StateStore};
+pub use script::trace::{TraceEvent, TraceSink};
 pub use script::executor::{Executor, ExecutionResult};
 pub use exercises::{TutorialExercise, DrillExercise, SpeedTestExercise, ExerciseOutcome};
+pub use typing::{TypingOptions, TypingSession};
 pub use performance::{PerformanceTracker, ExerciseResult, PerformanceGrade, SpeedRecords};
 pub use menu::{Menu, MenuItem};
+pub use terminal::{Backend, CrosstermBackend, MemoryBackend};
@@ -0,0 +1,320 @@
+//! Static lesson checker
+//!
+//! Finds problems in a lesson script without running it: jumps to labels
+//! that don't exist, duplicate labels, unreachable commands, answers with
+//! no question, out-of-range error thresholds and bad file references.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use crate::script::{Script, ScriptError, resolve_script_path};
+use crate::script::commands::{chars, Command, MENU_EXIT_LABEL};
+use crate::script::metadata::{self, DIRECTIVE_PREFIX};
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The script will misbehave or fail at runtime
+    Error,
+    /// The script runs but probably not as intended
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A single problem found in a script
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub severity: Severity,
+    /// Short machine-readable name of the check that fired
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}: {} [{}]", self.file, self.line, self.column, self.severity, self.message, self.code)
+    }
+}
+
+/// Parse and check a script file
+///
+/// Every line that fails to parse yields a `parse-error` diagnostic, and
+/// the rest of the script is still checked.
+pub fn lint_file(path: &str) -> Vec<Diagnostic> {
+    let (script, errors) = match Script::from_file_recovering(path) {
+        Ok(parsed) => parsed,
+        Err(e) => return vec![parse_error(path, e)],
+    };
+
+    let mut diagnostics: Vec<Diagnostic> = errors.into_iter().map(|e| parse_error(path, e)).collect();
+    diagnostics.extend(lint_script(&script));
+    diagnostics.sort_by_key(|d| (d.line, d.column));
+    diagnostics
+}
+
+fn parse_error(path: &str, error: ScriptError) -> Diagnostic {
+    let (file, line) = match &error {
+        ScriptError::InvalidCommand { line, .. } => (path.to_string(), *line),
+        ScriptError::Located { span, .. } => (span.file.clone(), span.line),
+        _ => (path.to_string(), 0),
+    };
+    Diagnostic {
+        file,
+        line,
+        column: 1,
+        severity: Severity::Error,
+        code: "parse-error",
+        message: match error {
+            ScriptError::Located { message, .. } => message,
+            other => other.to_string(),
+        },
+    }
+}
+
+/// Check a parsed script, returning diagnostics in source order
+pub fn lint_script(script: &Script) -> Vec<Diagnostic> {
+    let mut linter = Linter { script, diagnostics: Vec::new() };
+    linter.check_duplicate_labels();
+    linter.check_metadata();
+    linter.check_commands();
+    linter.diagnostics.sort_by_key(|d| (d.line, d.column));
+    linter.diagnostics
+}
+
+struct Linter<'a> {
+    script: &'a Script,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, index: usize, severity: Severity, code: &'static str, message: String) {
+        let (file, line, column) = match self.script.span(index) {
+            Some(span) => (span.file.clone(), span.line, span.column),
+            None => (self.script.path.clone(), 0, 0),
+        };
+        self.diagnostics.push(Diagnostic {
+            file,
+            line,
+            column,
+            severity,
+            code,
+            message,
+        });
+    }
+
+    fn check_duplicate_labels(&mut self) {
+        let mut first_seen: HashMap<&str, usize> = HashMap::new();
+
+        for (index, command) in self.script.commands.iter().enumerate() {
+            if let Command::Label { name } = command {
+                if let Some(&first) = first_seen.get(name.as_str()) {
+                    let first_line = self.script.span(first).map_or(0, |span| span.line);
+                    self.report(index, Severity::Error, "duplicate-label",
+                        format!("label '{}' is already defined on line {}", name, first_line));
+                } else {
+                    first_seen.insert(name, index);
+                }
+            }
+        }
+    }
+
+    fn check_metadata(&mut self) {
+        let entries: Vec<(usize, String)> = metadata::header_directives(&self.script.commands)
+            .filter(|(_, (key, _))| key.eq_ignore_ascii_case("entry"))
+            .map(|(index, (_, label))| (index, label.to_string()))
+            .collect();
+
+        for (index, label) in entries {
+            if !self.script.labels.contains_key(&label) {
+                self.report(index, Severity::Error, "undefined-label",
+                    format!("{}entry: target '{}' is not defined", DIRECTIVE_PREFIX, label));
+            }
+        }
+    }
+
+    fn check_label(&mut self, index: usize, command_char: char, label: &str) {
+        if !self.script.labels.contains_key(label) {
+            self.report(index, Severity::Error, "undefined-label",
+                format!("{}: target '{}' is not defined", command_char, label));
+        }
+    }
+
+    fn check_file(&mut self, index: usize, command_char: char, path: &str) {
+        if let Err(e) = resolve_script_path(path, self.script.source_file(index)) {
+            self.report(index, Severity::Error, "bad-file",
+                format!("{}: {}", command_char, e));
+        }
+    }
+
+    fn check_commands(&mut self) {
+        let mut after_jump: Option<char> = None;
+
+        for (index, command) in self.script.commands.iter().enumerate() {
+            // Anything but a label directly after G:, X: or R: can never run
+            match command {
+                Command::Comment { .. } => continue,
+                Command::Label { .. } => after_jump = None,
+                _ => {
+                    if let Some(jump_char) = after_jump.take() {
+                        self.report(index, Severity::Warning, "unreachable",
+                            format!("command is unreachable after {}:", jump_char));
+                    }
+                }
+            }
+
+            match command {
+                Command::Goto { label } => {
+                    self.check_label(index, chars::GOTO, label);
+                    after_jump = Some(chars::GOTO);
+                },
+                Command::Exit => after_jump = Some(chars::EXIT),
+                Command::Return => after_jump = Some(chars::RETURN),
+                Command::Call { label } => self.check_label(index, chars::CALL, label),
+                Command::YesGoto { label } => {
+                    self.check_label(index, chars::YES_GOTO, label);
+                    self.check_answer(index, chars::YES_GOTO);
+                },
+                Command::NoGoto { label } => {
+                    self.check_label(index, chars::NO_GOTO, label);
+                    self.check_answer(index, chars::NO_GOTO);
+                },
+                Command::OnFailureSet { label: Some(label), .. } => {
+                    self.check_label(index, chars::ON_FAILURE_SET, label);
+                },
+                Command::PerformanceGoto { label, .. } => {
+                    self.check_label(index, chars::PERFORMANCE_GOTO, label);
+                },
+                Command::KeyBind { label: Some(label), .. } => {
+                    self.check_label(index, chars::KEYBIND, label);
+                },
+                Command::Menu { up, items, .. } => {
+                    if let Some(up) = up.as_deref().filter(|&up| up != MENU_EXIT_LABEL) {
+                        self.check_label(index, chars::MENU, up);
+                    }
+                    for item in items {
+                        self.check_label(index, chars::MENU, &item.label);
+                    }
+                },
+                Command::ErrorMaxSet { percentage: Some(percentage), .. } if !(0.0..=100.0).contains(percentage) => {
+                    self.report(index, Severity::Error, "error-threshold",
+                        format!("E: value {} is outside 0-100", percentage));
+                },
+                Command::TutorialFile { path } => self.check_file(index, chars::TUTORIAL_FILE, path),
+                Command::DrillFile { path, practice_only } => {
+                    let command_char = if *practice_only { chars::DRILL_FILE_PRACTICE } else { chars::DRILL_FILE };
+                    self.check_file(index, command_char, path);
+                },
+                Command::SpeedTestFile { path, practice_only } => {
+                    let command_char = if *practice_only { chars::SPEEDTEST_FILE_PRACTICE } else { chars::SPEEDTEST_FILE };
+                    self.check_file(index, command_char, path);
+                },
+                _ => {},
+            }
+        }
+    }
+
+    /// Y: and N: only make sense straight after a Q: (possibly with the
+    /// other answer in between)
+    fn check_answer(&mut self, index: usize, command_char: char) {
+        let preceding = self.script.commands[..index].iter().rev()
+            .find(|c| !matches!(c, Command::Comment { .. } | Command::YesGoto { .. } | Command::NoGoto { .. }));
+
+        if !matches!(preceding, Some(Command::Query { .. })) {
+            self.report(index, Severity::Warning, "answer-without-query",
+                format!("{}: is not preceded by a Q: question", command_char));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    fn lint_source(dir: &TempDir, source: &str) -> Vec<Diagnostic> {
+        let path = dir.path().join("lesson.typ");
+        std::fs::File::create(&path).unwrap().write_all(source.as_bytes()).unwrap();
+        lint_file(path.to_str().unwrap())
+    }
+
+    fn codes(diagnostics: &[Diagnostic]) -> Vec<(usize, &'static str)> {
+        diagnostics.iter().map(|d| (d.line, d.code)).collect()
+    }
+
+    #[test]
+    fn test_clean_script() {
+        let dir = TempDir::new().unwrap();
+        let diagnostics = lint_source(&dir, "#@entry: START\n*:START\nF:START*\nE:default\nQ:Again?\nY:START\nN:END\nF:NULL\nG:END\n*:END\nX:\n");
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn test_reports_each_problem() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("ok.txt"), "text").unwrap();
+        let diagnostics = lint_source(&dir, r#"*:START
+K:12:MENU
+F:NOWHERE
+E:150%
+Y:START
+t:ok.txt
+f:missing.txt
+z:../escape.txt
+G:START
+T:never shown
+*:START
+M: "Menu"
+ :GONE "Gone"
+M: UP=NOWHERE "Submenu"
+ :START "Start"
+M: UP=_EXIT "Top"
+ :START "Start"
+"#);
+
+        assert_eq!(codes(&diagnostics), vec![
+            (2, "undefined-label"),
+            (3, "undefined-label"),
+            (4, "error-threshold"),
+            (5, "answer-without-query"),
+            (7, "bad-file"),
+            (8, "bad-file"),
+            (10, "unreachable"),
+            (11, "duplicate-label"),
+            (12, "undefined-label"),
+            (14, "undefined-label"),
+        ]);
+        assert!(diagnostics[0].to_string().ends_with(":2:1: error: K: target 'MENU' is not defined [undefined-label]"));
+    }
+
+    #[test]
+    fn test_missing_entry_label() {
+        let dir = TempDir::new().unwrap();
+        let diagnostics = lint_source(&dir, "# Lesson\n#@entry: BEGIN\n*:START\nX:\n");
+        assert_eq!(codes(&diagnostics), vec![(2, "undefined-label")]);
+        assert_eq!(diagnostics[0].message, "#@entry: target 'BEGIN' is not defined");
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let dir = TempDir::new().unwrap();
+        let diagnostics = lint_source(&dir, "*:START\nthis is not a command\nG:NOWHERE\nK:99:START\n");
+        assert_eq!(codes(&diagnostics), vec![
+            (2, "parse-error"),
+            (3, "undefined-label"),
+            (4, "parse-error"),
+        ]);
+    }
+}
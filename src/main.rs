@@ -3,27 +3,49 @@
 //! A typing tutor program that teaches touch typing through structured lessons.
 //! This is a Rust port of the original C implementation of GNU Typist.
 
-use clap::{App, Arg, ArgMatches};
-use gtypist_rs::{Script, Executor, ExecutionResult, TutorialExercise, DrillExercise, SpeedTestExercise, ExerciseOutcome};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use gtypist_rs::{LessonSearch, Script, Executor, ExecutionResult, TutorialExercise, DrillExercise, SpeedTestExercise, ExerciseOutcome, Backend, CrosstermBackend, TraceSink, TypingOptions};
+use gtypist_rs::coverage::Coverage;
+use gtypist_rs::debugger::{DebugAction, Debugger};
+use gtypist_rs::lint::{self, Diagnostic, Severity};
+use gtypist_rs::runner::{self, RunOutcome};
+use gtypist_rs::Command;
+use gtypist_rs::script::search::DEFAULT_LESSON;
+use gtypist_rs::script::state::{self, StateStore};
+use gtypist_rs::script::structured::{self, LessonFormat};
+use gtypist_rs::script::commands::TypingOption;
+use gtypist_rs::script::trace;
+use gtypist_rs::terminal::{write_centered, write_line};
 use std::path::Path;
 use std::process;
 use std::fs;
 use crossterm::{
     execute,
+    event::{Event, KeyCode, KeyEvent},
     terminal::{Clear, ClearType, disable_raw_mode, enable_raw_mode},
     cursor,
 };
-use std::io::{stdout, Write};
+use std::io::stdout;
 
 fn main() {
     let matches = create_cli().get_matches();
     
-    // Run application (raw mode will be enabled when needed)
-    let result = run_application(&matches);
-    
-    // Cleanup terminal (in case raw mode was enabled)
-    let _ = disable_raw_mode();
-    let _ = execute!(stdout(), Clear(ClearType::All), cursor::MoveTo(0, 0));
+    let result = match matches.subcommand() {
+        ("run", Some(run_matches)) => run_headless(run_matches),
+        ("lint", Some(lint_matches)) => run_lint(lint_matches),
+        ("convert", Some(convert_matches)) => run_convert(convert_matches),
+        ("coverage", Some(coverage_matches)) => run_coverage(coverage_matches),
+        _ if matches.is_present("list-lessons") => list_lessons(),
+        _ => {
+            // Run application (raw mode will be enabled when needed)
+            let result = run_application(&matches);
+            
+            // Cleanup terminal (in case raw mode was enabled)
+            let _ = disable_raw_mode();
+            let _ = execute!(stdout(), Clear(ClearType::All), cursor::MoveTo(0, 0));
+            result
+        },
+    };
     
     if let Err(e) = result {
         eprintln!("Error: {}", e);
@@ -37,9 +59,12 @@ fn create_cli() -> App<'static, 'static> {
         .author("GNU Typist Team <bug-gtypist@gnu.org>")
         .about("A typing tutor program that teaches touch typing")
         .arg(Arg::with_name("lesson")
-            .help("Lesson file to load (.typ)")
+            .help("Lesson to load (.typ, .json or .toml); looked for in the current directory, $GTYPIST_PATH and the lesson directory")
             .required(false)
             .index(1))
+        .arg(Arg::with_name("list-lessons")
+            .long("list-lessons")
+            .help("List the lessons found on the search path"))
         .arg(Arg::with_name("label")
             .short("l")
             .long("label")
@@ -73,6 +98,288 @@ fn create_cli() -> App<'static, 'static> {
             .possible_values(&["tutorial", "drill", "speedtest"])
             .default_value("drill")
             .takes_value(true))
+        .arg(max_error_arg())
+        .args(&typing_option_args())
+        .arg(Arg::with_name("debug")
+            .long("debug")
+            .help("Step through the lesson one command at a time, showing executor state"))
+        .arg(Arg::with_name("break")
+            .long("break")
+            .value_name("LABEL")
+            .help("Pause in the debugger on reaching LABEL (may be repeated)")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1))
+        .arg(trace_arg())
+        .subcommand(SubCommand::with_name("run")
+            .about("Run a lesson headless, reading keystrokes from a file")
+            .arg(Arg::with_name("script")
+                .help("Lesson to run, looked up like the interactive lesson argument")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("keys")
+                .long("keys")
+                .value_name("FILE")
+                .help("Timestamped key file supplying the input")
+                .required(true)
+                .takes_value(true))
+            .arg(Arg::with_name("label")
+                .short("l")
+                .long("label")
+                .value_name("LABEL")
+                .help("Start at specific label in lesson")
+                .takes_value(true))
+            .arg(max_error_arg())
+            .args(&typing_option_args())
+            .arg(trace_arg()))
+        .subcommand(SubCommand::with_name("lint")
+            .about("Check lesson files for errors without running them")
+            .arg(Arg::with_name("files")
+                .help("Lesson files to check (.typ)")
+                .required(true)
+                .multiple(true)
+                .index(1))
+            .arg(Arg::with_name("json")
+                .long("json")
+                .help("Print diagnostics as a JSON array")))
+        .subcommand(SubCommand::with_name("convert")
+            .about("Convert a lesson between .typ, JSON and TOML")
+            .arg(Arg::with_name("input")
+                .help("Lesson file to read (.typ, .json or .toml)")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("output")
+                .help("File to write; the format follows its extension. Prints to stdout if omitted")
+                .index(2))
+            .arg(Arg::with_name("to")
+                .long("to")
+                .value_name("FORMAT")
+                .help("Output format, overriding the output file extension")
+                .possible_values(&["typ", "json", "toml"])
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("coverage")
+            .about("Show which labels and exercises of a lesson traced runs reached and completed")
+            .arg(Arg::with_name("lesson")
+                .help("Lesson to report on, looked up like the interactive lesson argument")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("traces")
+                .help("Trace files written with --trace while running the lesson")
+                .required(true)
+                .multiple(true)
+                .index(2)))
+}
+
+/// `-e/--max-error`, shared by interactive and headless runs
+fn max_error_arg() -> Arg<'static, 'static> {
+    Arg::with_name("max-error")
+        .short("e")
+        .long("max-error")
+        .value_name("PERCENT")
+        .help("Default error percentage allowed in drills; E: in the lesson can only lower it")
+        .takes_value(true)
+        .validator(|value| match value.parse::<f32>() {
+            Ok(percentage) if (0.0..=100.0).contains(&percentage) => Ok(()),
+            _ => Err(format!("'{}' is not a percentage between 0 and 100", value)),
+        })
+}
+
+/// Apply `--max-error` to an executor, if it was given
+fn apply_max_error(matches: &ArgMatches, executor: &mut Executor) {
+    if let Some(percentage) = matches.value_of("max-error").and_then(|v| v.parse().ok()) {
+        executor.set_max_error(percentage);
+    }
+}
+
+/// Typing option flags, shared by interactive and headless runs
+fn typing_option_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("word-processor")
+            .short("w")
+            .long("word-processor")
+            .help("Type as in a word processor: one space or Enter covers any run of spaces and line breaks, and hyphens at line ends can be skipped"),
+        Arg::with_name("sync-ahead")
+            .short("a")
+            .long("sync-ahead")
+            .help("Turn off sync-ahead, which is on by default and recovers from a dropped or doubled character so the slip counts as one error"),
+        Arg::with_name("stop-on-error")
+            .long("stop-on-error")
+            .help("Keep the cursor on a wrong key until the right one is pressed"),
+        Arg::with_name("show-errors")
+            .short("i")
+            .long("show-errors")
+            .help("Show wrong characters in reverse video"),
+    ]
+}
+
+/// `options` with those given on the command line switched from their
+/// defaults, as gengetopt flags are in C gtypist
+///
+/// Each flag is named after its option, so this covers every one of them.
+fn typing_options(matches: &ArgMatches, mut options: TypingOptions) -> TypingOptions {
+    let defaults = TypingOptions::default();
+    for option in TypingOption::ALL {
+        if matches.is_present(option.name()) {
+            options.set(option, !defaults.is_set(option));
+        }
+    }
+    options
+}
+
+/// `--trace FILE`, shared by interactive and headless runs
+fn trace_arg() -> Arg<'static, 'static> {
+    Arg::with_name("trace")
+        .long("trace")
+        .value_name("FILE")
+        .help("Log every command, jump and exercise outcome to FILE as JSON lines")
+        .takes_value(true)
+}
+
+/// Attach the `--trace` file to an executor, if one was given
+fn apply_trace(matches: &ArgMatches, executor: Executor) -> Result<Executor, Box<dyn std::error::Error>> {
+    Ok(match matches.value_of("trace") {
+        Some(path) => executor.with_trace(TraceSink::create(path)?),
+        None => executor,
+    })
+}
+
+/// Label to start at: `--label` if given, else the lesson's `#@entry:`
+fn start_label(matches: &ArgMatches, script: &Script) -> Option<String> {
+    matches.value_of("label").map(str::to_string).or_else(|| script.metadata.entry.clone())
+}
+
+/// Print every lesson on the search path, with its title if it has one
+fn list_lessons() -> Result<(), Box<dyn std::error::Error>> {
+    for (dir, lessons) in LessonSearch::from_env().lessons() {
+        if lessons.is_empty() {
+            continue;
+        }
+        println!("{}:", dir.display());
+        for lesson in lessons {
+            let name = lesson.file_name().unwrap_or_default().to_string_lossy();
+            let title = Script::from_file(&lesson.to_string_lossy()).ok().and_then(|script| script.metadata.title);
+            match title {
+                Some(title) => println!("  {:<16} {}", name, title),
+                None => println!("  {}", name),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Convert a lesson file to another format
+fn run_convert(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let input = matches.value_of("input").unwrap();
+    let output = matches.value_of("output");
+    
+    let format = match (matches.value_of("to"), output) {
+        (Some(name), _) => LessonFormat::from_name(name).unwrap(),
+        (None, Some(path)) => LessonFormat::from_path(path),
+        (None, None) => return Err("--to is required when writing to stdout".into()),
+    };
+    
+    let script = Script::from_file(input)?;
+    let converted = structured::write_lesson(&script, format)?;
+    
+    match output {
+        Some(path) => fs::write(path, converted)?,
+        None => print!("{}", converted),
+    }
+    Ok(())
+}
+
+/// Print a lesson outline marked with how far the given traces got
+fn run_coverage(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let lesson_file = LessonSearch::from_env().find(matches.value_of("lesson").unwrap())?;
+    let script = Script::from_file(&lesson_file.to_string_lossy())?;
+    
+    let mut coverage = Coverage::new(&script);
+    for trace_file in matches.values_of("traces").unwrap() {
+        coverage.add_trace(&script, &trace::read_trace(trace_file)?);
+    }
+    
+    println!("Coverage of {} from {} trace(s)", script.path, coverage.traces);
+    println!();
+    for line in coverage.outline(&script) {
+        println!("{}", line);
+    }
+    println!();
+    println!("{}", coverage.summary(&script));
+    if coverage.mismatched > 0 {
+        println!("{} trace event(s) did not match this lesson and were ignored", coverage.mismatched);
+    }
+    Ok(())
+}
+
+/// Check lesson files and print any problems found
+fn run_lint(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let diagnostics: Vec<Diagnostic> = matches.values_of("files")
+        .unwrap()
+        .flat_map(lint::lint_file)
+        .collect();
+    
+    if matches.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&diagnostics)?);
+    } else {
+        for diagnostic in &diagnostics {
+            println!("{}", diagnostic);
+        }
+    }
+    
+    let errors = diagnostics.iter().filter(|d| d.severity == Severity::Error).count();
+    let warnings = diagnostics.len() - errors;
+    if !matches.is_present("json") {
+        println!("{} error(s), {} warning(s)", errors, warnings);
+    }
+    
+    if errors > 0 {
+        return Err(format!("{} error(s) found", errors).into());
+    }
+    Ok(())
+}
+
+/// Run a lesson without a terminal and print the path taken through it
+fn run_headless(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let script_file = LessonSearch::from_env().find(matches.value_of("script").unwrap())?;
+    let key_file = matches.value_of("keys").unwrap();
+    
+    let script = Script::from_file(&script_file.to_string_lossy())?;
+    let events = runner::load_key_file(key_file)?;
+    let executor = Executor::with_backend(script, Box::new(runner::backend_from_events(events)));
+    let mut executor = apply_trace(matches, executor)?;
+    apply_max_error(matches, &mut executor);
+    executor.typing_options = typing_options(matches, executor.typing_options);
+    
+    if let Some(label) = start_label(matches, &executor.script) {
+        executor.script.goto_label(&label)?;
+    }
+    
+    let report = runner::run(&mut executor)?;
+    
+    println!("Label path: {}", report.labels.join(" -> "));
+    println!();
+    println!("Exercise results:");
+    if report.results.is_empty() {
+        println!("  (none)");
+    }
+    for (i, result) in report.results.iter().enumerate() {
+        println!("  {:>3}. {} chars, {} correct, {} errors, {:.1}% accuracy, {:.1} WPM, {:.1}s",
+            i + 1, result.total_chars, result.correct_chars, result.errors,
+            result.accuracy(), result.wpm, result.duration.as_secs_f32());
+    }
+    println!();
+    
+    let outcome = match report.outcome {
+        RunOutcome::Finished => "reached end of script",
+        RunOutcome::Exit => "exited",
+        RunOutcome::InputExhausted => "ran out of input",
+    };
+    match executor.script.span(executor.script.position) {
+        Some(span) => println!("Outcome: {} at {}", outcome, span),
+        None => println!("Outcome: {} at command {}", outcome, executor.script.position),
+    }
+    
+    Ok(())
 }
 
 fn run_application(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
@@ -81,16 +388,12 @@ fn run_application(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error
         return run_text_file_mode(text_file, matches);
     }
     
-    let lesson_file = matches.value_of("lesson").unwrap_or("lessons/gtypist.typ");
-    let start_label = matches.value_of("label");
-    
-    // Check if lesson file exists
-    if !Path::new(lesson_file).exists() {
-        return Err(format!("Lesson file not found: {}", lesson_file).into());
-    }
+    // Find the lesson before touching the terminal
+    let lesson_file = LessonSearch::from_env().find(matches.value_of("lesson").unwrap_or(DEFAULT_LESSON))?;
     
     // Display welcome message (before enabling raw mode)
-    display_welcome()?;
+    let mut backend = CrosstermBackend::new();
+    display_welcome(&mut backend)?;
     
     // Now enable raw mode for interactive parts
     if let Err(e) = enable_raw_mode() {
@@ -98,35 +401,68 @@ fn run_application(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error
     }
     
     // Parse and execute the lesson script
-    let script = Script::from_file(lesson_file)?;
-    let mut executor = Executor::new(script);
+    let script = Script::from_file(&lesson_file.to_string_lossy())?;
+    let mut executor = apply_trace(matches, Executor::with_backend(script, Box::new(backend)))?;
+    
+    // --break alone runs freely until the first breakpoint
+    let mut debugger = (matches.is_present("debug") || matches.is_present("break")).then(|| {
+        let breakpoints = matches.values_of("break").into_iter().flatten().map(str::to_string);
+        Debugger::new(breakpoints, matches.is_present("debug"))
+    });
+    
+    // Offer to pick up where the last session on this lesson stopped,
+    // unless a start label was asked for. Debug runs leave saved
+    // progress alone.
+    let store = if debugger.is_some() { None } else { StateStore::from_env() };
+    let saved = store.as_ref()
+        .filter(|_| matches.value_of("label").is_none())
+        .and_then(|store| store.load(&executor.script.path).ok().flatten())
+        .filter(|saved| state::content_hash(&executor.script).ok().as_ref() == Some(&saved.content_hash));
+    let resumed = match saved {
+        Some(saved) if ask_resume(executor.backend.as_mut())? => executor.resume(saved).is_ok(),
+        _ => false,
+    };
+    // After resuming, so a stricter E: from the session stays in force
+    apply_max_error(matches, &mut executor);
+    executor.typing_options = typing_options(matches, executor.typing_options);
     
     // Jump to start label if specified
-    if let Some(label) = start_label {
-        executor.script.goto_label(label)?;
+    if !resumed {
+        if let Some(label) = start_label(matches, &executor.script) {
+            executor.script.goto_label(&label)?;
+        }
     }
     
     // Main execution loop
     loop {
+        if let Some(debugger) = debugger.as_mut() {
+            if debugger.before_command(&mut executor)? == DebugAction::Quit {
+                display_goodbye(executor.backend.as_mut())?;
+                break;
+            }
+        }
+        
         match executor.execute_next()? {
             ExecutionResult::Continue => {
                 // Continue to next command
             },
             ExecutionResult::Exit => {
-                display_goodbye()?;
+                save_progress(store.as_ref(), &executor);
+                display_goodbye(executor.backend.as_mut())?;
                 break;
             },
             ExecutionResult::Finished => {
-                display_completion()?;
+                save_progress(store.as_ref(), &executor);
+                display_completion(executor.backend.as_mut())?;
                 break;
             },
             ExecutionResult::WaitForInput => {
                 // Wait for user input
-                use crossterm::event::{read, Event, KeyCode, KeyEvent};
                 loop {
-                    match read()? {
+                    match executor.backend.read_event()? {
                         Event::Key(KeyEvent { code: KeyCode::Esc, .. }) => {
-                            display_goodbye()?;
+                            save_progress(store.as_ref(), &executor);
+                            display_goodbye(executor.backend.as_mut())?;
                             return Ok(());
                         },
                         Event::Key(_) => break, // Any other key continues
@@ -134,7 +470,7 @@ fn run_application(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error
                     }
                 }
             },
-            ExecutionResult::Jump(_) => {
+            ExecutionResult::Jump(_) | ExecutionResult::Repeat => {
                 // This should not happen at this level since it's handled in execute_next
                 continue;
             },
@@ -142,7 +478,8 @@ fn run_application(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error
         
         // Check if script is finished
         if executor.script.is_finished() {
-            display_completion()?;
+            save_progress(store.as_ref(), &executor);
+            display_completion(executor.backend.as_mut())?;
             break;
         }
     }
@@ -150,6 +487,40 @@ fn run_application(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error
     Ok(())
 }
 
+/// Remember where the user stopped, or forget it once the lesson is done
+///
+/// Failing to save only costs the user their place, so it doesn't end
+/// the session with an error.
+fn save_progress(store: Option<&StateStore>, executor: &Executor) {
+    let Some(store) = store else { return };
+    let done = executor.script.is_finished() || matches!(executor.script.current_command(), Some(Command::Exit));
+    let _ = if done {
+        store.remove(&executor.script.path)
+    } else {
+        executor.state().and_then(|state| store.save(&state))
+    };
+}
+
+/// Ask whether to continue an unfinished session on the lesson
+fn ask_resume(backend: &mut dyn Backend) -> Result<bool, Box<dyn std::error::Error>> {
+    backend.clear_screen()?;
+    
+    write_line(backend, "")?;
+    write_centered(backend, "You left this lesson unfinished last time.")?;
+    write_line(backend, "")?;
+    write_centered(backend, "Resume where you left off? (Y/N)")?;
+    write_line(backend, "")?;
+    backend.flush()?;
+    
+    loop {
+        match backend.read_event()? {
+            Event::Key(KeyEvent { code: KeyCode::Char('y' | 'Y'), .. }) => return Ok(true),
+            Event::Key(KeyEvent { code: KeyCode::Char('n' | 'N') | KeyCode::Esc, .. }) => return Ok(false),
+            _ => continue,
+        }
+    }
+}
+
 /// Run in text file mode - create a simple exercise from an arbitrary text file
 fn run_text_file_mode(text_file: &str, matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
     // Check if text file exists
@@ -165,7 +536,8 @@ fn run_text_file_mode(text_file: &str, matches: &ArgMatches) -> Result<(), Box<d
     let mode = matches.value_of("mode").unwrap_or("drill");
     
     // Display welcome message
-    display_welcome()?;
+    let mut backend = CrosstermBackend::new();
+    display_welcome(&mut backend)?;
     
     // Enable raw mode for interactive exercises
     if let Err(e) = enable_raw_mode() {
@@ -176,15 +548,17 @@ fn run_text_file_mode(text_file: &str, matches: &ArgMatches) -> Result<(), Box<d
     let outcome = match mode {
         "tutorial" => {
             let exercise = TutorialExercise::new(text_content);
-            exercise.execute()?
+            exercise.execute(&mut backend)?
         },
         "drill" => {
-            let exercise = DrillExercise::new(text_content, false, 0.0); // No error limit for direct file mode
-            exercise.execute()?
+            let exercise = DrillExercise::new(text_content, false, 100.0) // No error limit for direct file mode
+                .with_options(typing_options(matches, TypingOptions::default()));
+            exercise.execute(&mut backend)?
         },
         "speedtest" => {
-            let exercise = SpeedTestExercise::new(text_content, false, None);
-            exercise.execute()?
+            let exercise = SpeedTestExercise::new(text_content, false, None)
+                .with_options(typing_options(matches, TypingOptions::default()));
+            exercise.execute(&mut backend)?
         },
         _ => unreachable!(), // clap validates this
     };
@@ -192,63 +566,36 @@ fn run_text_file_mode(text_file: &str, matches: &ArgMatches) -> Result<(), Box<d
     // Handle the outcome
     match outcome {
         ExerciseOutcome::Completed(_) => {
-            display_completion()?;
+            display_completion(&mut backend)?;
         },
         ExerciseOutcome::Quit => {
-            display_goodbye()?;
+            display_goodbye(&mut backend)?;
         },
         _ => {
-            display_goodbye()?;
+            display_goodbye(&mut backend)?;
         }
     }
     
     Ok(())
 }
 
-/// Helper function to center text in terminal
-fn center_text(text: &str) -> String {
-    // Try to get terminal size, fall back to reasonable defaults
-    let width = match crossterm::terminal::size() {
-        Ok((w, _)) => w as usize,
-        Err(_) => {
-            // If we can't get terminal size, try environment variables or use 80
-            std::env::var("COLUMNS")
-                .ok()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(80)
-        }
-    };
-    
-    let text_len = text.chars().count(); // Use char count for proper Unicode handling
-    if text_len >= width {
-        return text.to_string(); // Don't try to center if text is too long
-    }
-    
-    let padding = (width - text_len) / 2;
-    format!("{}{}", " ".repeat(padding), text)
-}
-
-fn display_welcome() -> Result<(), Box<dyn std::error::Error>> {
-    let mut stdout = stdout();
-    
-    // Clear screen but don't use raw mode yet
-    print!("\x1B[2J\x1B[1;1H"); // ANSI escape codes for clear screen and move cursor
-    
-    println!();
-    println!("{}", center_text("=== GNU Typist - Rust Implementation ===")); 
-    println!("{}", center_text("Version 0.1.0"));
-    println!();
-    println!("{}", center_text("A typing tutor to help you learn touch typing."));
-    println!("{}", center_text("Press ESC at any time to exit."));
-    println!();
-    println!("{}", center_text("Press any key to continue..."));
-    println!();
-    stdout.flush()?;
+fn display_welcome(backend: &mut dyn Backend) -> Result<(), Box<dyn std::error::Error>> {
+    backend.clear_screen()?;
+    
+    write_line(backend, "")?;
+    write_centered(backend, "=== GNU Typist - Rust Implementation ===")?;
+    write_centered(backend, "Version 0.1.0")?;
+    write_line(backend, "")?;
+    write_centered(backend, "A typing tutor to help you learn touch typing.")?;
+    write_centered(backend, "Press ESC at any time to exit.")?;
+    write_line(backend, "")?;
+    write_centered(backend, "Press any key to continue...")?;
+    write_line(backend, "")?;
+    backend.flush()?;
     
     // Wait for user input
-    use crossterm::event::{read, Event, KeyCode, KeyEvent};
     loop {
-        match read()? {
+        match backend.read_event()? {
             Event::Key(KeyEvent { code: KeyCode::Esc, .. }) => {
                 return Err("User cancelled".into());
             },
@@ -260,58 +607,43 @@ fn display_welcome() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-fn display_goodbye() -> Result<(), Box<dyn std::error::Error>> {
-    let mut stdout = stdout();
-    
-    // Clear screen with direct ANSI codes
-    print!("\x1B[2J\x1B[1;1H");
-    
-    println!();
-    println!("{}", center_text("=== GNU Typist ==="));
-    println!();
-    print!("\x1B[1GThanks for using GNU Typist!\n");
-    print!("\x1B[1GKeep practicing to improve your typing skills.\n");
-    println!();
-    print!("\x1B[1GPress any key to exit...\n");
-    println!();
-    stdout.flush()?;
-    
-    // Wait for user input
-    use crossterm::event::{read, Event, KeyEvent};
-    loop {
-        match read()? {
-            Event::Key(KeyEvent { .. }) => break,
-            _ => continue,
-        }
-    }
-    
-    Ok(())
+fn display_goodbye(backend: &mut dyn Backend) -> Result<(), Box<dyn std::error::Error>> {
+    backend.clear_screen()?;
+    
+    write_line(backend, "")?;
+    write_centered(backend, "=== GNU Typist ===")?;
+    write_line(backend, "")?;
+    write_line(backend, "Thanks for using GNU Typist!")?;
+    write_line(backend, "Keep practicing to improve your typing skills.")?;
+    write_line(backend, "")?;
+    write_line(backend, "Press any key to exit...")?;
+    write_line(backend, "")?;
+    backend.flush()?;
+    
+    wait_for_key(backend)
 }
 
-fn display_completion() -> Result<(), Box<dyn std::error::Error>> {
-    let mut stdout = stdout();
-    
-    // Clear screen with direct ANSI codes
-    print!("\x1B[2J\x1B[1;1H");
-    
-    println!();
-    println!("{}", center_text("=== Lesson Complete ==="));
-    println!();
-    print!("\x1B[1GCongratulations! You have completed this lesson.\n");
-    print!("\x1B[1GContinue practicing to improve your typing skills.\n");
-    println!();
-    print!("\x1B[1GPress any key to exit...\n");
-    println!();
-    stdout.flush()?;
-    
-    // Wait for user input
-    use crossterm::event::{read, Event, KeyEvent};
+fn display_completion(backend: &mut dyn Backend) -> Result<(), Box<dyn std::error::Error>> {
+    backend.clear_screen()?;
+    
+    write_line(backend, "")?;
+    write_centered(backend, "=== Lesson Complete ===")?;
+    write_line(backend, "")?;
+    write_line(backend, "Congratulations! You have completed this lesson.")?;
+    write_line(backend, "Continue practicing to improve your typing skills.")?;
+    write_line(backend, "")?;
+    write_line(backend, "Press any key to exit...")?;
+    write_line(backend, "")?;
+    backend.flush()?;
+    
+    wait_for_key(backend)
+}
+
+/// Block until any key is pressed
+fn wait_for_key(backend: &mut dyn Backend) -> Result<(), Box<dyn std::error::Error>> {
     loop {
-        match read()? {
-            Event::Key(KeyEvent { .. }) => break,
-            _ => continue,
+        if let Event::Key(_) = backend.read_event()? {
+            return Ok(());
         }
     }
-    
-    Ok(())
-}
\ No newline at end of file
+}
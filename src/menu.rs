@@ -4,12 +4,11 @@
 //! and navigate through the typing tutor interface.
 
 use crossterm::{
-    cursor, execute,
+    QueueableCommand,
     style::{Color, ResetColor, SetForegroundColor},
-    terminal::{Clear, ClearType},
-    event::{read, Event, KeyCode, KeyEvent},
+    event::{Event, KeyCode, KeyEvent},
 };
-use std::io::{stdout, Write};
+use crate::terminal::{Backend, center_text, write_line};
 
 /// Menu item representing a selectable option
 #[derive(Debug, Clone, PartialEq)]
@@ -40,27 +39,18 @@ impl Menu {
     }
     
     /// Display menu and handle user selection
-    pub fn display(&self) -> Result<Option<String>, Box<dyn std::error::Error>> {
-        let mut stdout = stdout();
+    pub fn display(&self, backend: &mut dyn Backend) -> Result<Option<String>, Box<dyn std::error::Error>> {
         let mut selected = 0;
         
         loop {
             // Clear screen and display menu
-            execute!(stdout, Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+            backend.clear_screen()?;
             
             // Display title (centered)
             let title_text = format!("=== {} ===", self.title);
-            let width = match crossterm::terminal::size() {
-                Ok((w, _)) => w as usize,
-                Err(_) => 80
-            };
-            let padding = if title_text.len() < width {
-                (width - title_text.len()) / 2
-            } else {
-                0
-            };
-            println!("{}{}", " ".repeat(padding), title_text);
-            println!();
+            let width = backend.size().0 as usize;
+            write_line(backend, &center_text(&title_text, width))?;
+            write_line(backend, "")?;
             
             // Find the maximum label width for consistent alignment
             let max_label_width = self.items.iter()
@@ -73,24 +63,22 @@ impl Menu {
             for (i, item) in self.items.iter().enumerate() {
                 if i == selected {
                     // Highlight selected item
-                    execute!(stdout, SetForegroundColor(Color::Yellow))?;
-                    print!("\x1B[1G  > {:<width$} {}\n", item.label, item.title, width = max_label_width);
-                    execute!(stdout, ResetColor)?;
+                    backend.queue(SetForegroundColor(Color::Yellow))?;
+                    write_line(backend, &format!("  > {:<width$} {}", item.label, item.title, width = max_label_width))?;
+                    backend.queue(ResetColor)?;
                 } else {
-                    print!("\x1B[1G    {:<width$} {}\n", item.label, item.title, width = max_label_width);
+                    write_line(backend, &format!("    {:<width$} {}", item.label, item.title, width = max_label_width))?;
                 }
             }
             
-            println!();
-            print!("\x1B[1GUse UP/DOWN arrows to navigate, ENTER to select, ESC to quit\n");
-            stdout.flush()?;
+            write_line(backend, "")?;
+            write_line(backend, "Use UP/DOWN arrows to navigate, ENTER to select, ESC to go back")?;
+            backend.flush()?;
             
             // Handle user input
-            match read()? {
+            match backend.read_event()? {
                 Event::Key(KeyEvent { code: KeyCode::Up, .. }) => {
-                    if selected > 0 {
-                        selected -= 1;
-                    }
+                    selected = selected.saturating_sub(1);
                 },
                 Event::Key(KeyEvent { code: KeyCode::Down, .. }) => {
                     if selected < self.items.len().saturating_sub(1) {
@@ -151,6 +139,7 @@ pub fn parse_menu_item_line(line: &str) -> Option<MenuItem> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::terminal::MemoryBackend;
     
     #[test]
     fn test_parse_menu_item() {
@@ -178,4 +167,20 @@ mod tests {
         assert_eq!(menu.items[0].label, "ITEM1");
         assert_eq!(menu.items[1].title, "Second Item");
     }
+    
+    #[test]
+    fn test_menu_display_headless() {
+        let mut menu = Menu::new("Test Menu".to_string());
+        menu.add_item("ITEM1".to_string(), "First Item".to_string());
+        menu.add_item("ITEM2".to_string(), "Second Item".to_string());
+        
+        let mut backend = MemoryBackend::new();
+        backend.push_key(KeyCode::Down);
+        backend.push_key(KeyCode::Enter);
+        assert_eq!(menu.display(&mut backend).unwrap(), Some("ITEM2".to_string()));
+        assert!(backend.plain_output().contains("=== Test Menu ==="));
+        
+        backend.push_key(KeyCode::Esc);
+        assert_eq!(menu.display(&mut backend).unwrap(), None);
+    }
 }
\ No newline at end of file
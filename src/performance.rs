@@ -11,6 +11,7 @@ use serde::{Deserialize, Serialize};
 pub struct PerformanceTracker {
     correct_chars: usize,
     errors: usize,
+    slips: usize,
     backspaces: usize,
     start_time: Option<Instant>,
     duration: Option<Duration>,
@@ -22,6 +23,7 @@ impl PerformanceTracker {
         Self {
             correct_chars: 0,
             errors: 0,
+            slips: 0,
             backspaces: 0,
             start_time: None,
             duration: None,
@@ -47,6 +49,15 @@ impl PerformanceTracker {
         self.errors += 1;
     }
     
+    /// Record a slip that sync-ahead recovered from: characters left out,
+    /// or a key pressed twice
+    ///
+    /// However far it put the typist out of step, a slip is one error.
+    pub fn record_slip(&mut self) {
+        self.record_error();
+        self.slips += 1;
+    }
+    
     /// Record a backspace operation
     pub fn record_backspace(&mut self) {
         self.backspaces += 1;
@@ -113,6 +124,11 @@ impl PerformanceTracker {
         self.errors
     }
     
+    /// Get the number of errors that were slips
+    pub fn slips(&self) -> usize {
+        self.slips
+    }
+    
     /// Get backspace count  
     pub fn backspaces(&self) -> usize {
         self.backspaces
@@ -141,9 +157,9 @@ pub struct ExerciseResult {
     pub error_rate: f32,
 }
 
-impl ExerciseResult {
+impl Default for ExerciseResult {
     /// Create a default result (for tutorials)
-    pub fn default() -> Self {
+    fn default() -> Self {
         Self {
             total_chars: 0,
             correct_chars: 0,
@@ -153,7 +169,9 @@ impl ExerciseResult {
             error_rate: 0.0,
         }
     }
-    
+}
+
+impl ExerciseResult {
     /// Calculate accuracy percentage
     pub fn accuracy(&self) -> f32 {
         100.0 - self.error_rate
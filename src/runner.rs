@@ -0,0 +1,245 @@
+//! Headless lesson runner
+//!
+//! Drives a script through `Executor::execute_next` with input taken from
+//! a timestamped key file instead of the terminal, recording the labels
+//! visited and every exercise result along the way.
+//!
+//! Key files contain one event per line in the form `<milliseconds> <key>`:
+//!
+//! ```text
+//! # Timestamps are milliseconds since the start of the run
+//! 0     <Space>
+//! 1200  a
+//! 1350  "sdf ;lkj"
+//! 4000  <Enter>
+//! ```
+//!
+//! A key is a single character, a quoted string (one key press per
+//! character, all at the same timestamp), or a named key in angle
+//! brackets: `<Space>`, `<Enter>`, `<Tab>`, `<Esc>`, `<Backspace>`,
+//! `<Up>`, `<Down>`, `<Left>`, `<Right>`, `<F1>`..`<F12>` and `<C-x>`
+//! for Ctrl plus a character.
+
+use std::fs;
+use std::io;
+use std::time::Duration;
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use crate::performance::ExerciseResult;
+use crate::script::{ScriptError, ScriptResult};
+use crate::script::commands::Command;
+use crate::script::executor::{Executor, ExecutionResult};
+use crate::terminal::MemoryBackend;
+
+/// Upper bound on executed commands, so scripts that loop without
+/// consuming input cannot hang the runner
+const MAX_STEPS: usize = 1_000_000;
+
+/// How a headless run ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunOutcome {
+    /// Execution reached the end of the script
+    Finished,
+    /// The script or the user's input exited
+    Exit,
+    /// The key file ran out before the script finished
+    InputExhausted,
+}
+
+/// Everything observed during a headless run
+#[derive(Debug, Clone)]
+pub struct RunReport {
+    /// Labels in the order execution passed through them
+    pub labels: Vec<String>,
+    /// Results of every finished drill and speed test, failed ones included
+    pub results: Vec<ExerciseResult>,
+    /// How the run ended
+    pub outcome: RunOutcome,
+}
+
+/// Run an executor until the script finishes, exits or runs out of input
+pub fn run(executor: &mut Executor) -> ScriptResult<RunReport> {
+    let mut labels = Vec::new();
+
+    for _ in 0..MAX_STEPS {
+        if let Some(Command::Label { name }) = executor.script.current_command() {
+            labels.push(name.clone());
+        }
+
+        let result = match executor.execute_next() {
+            Ok(result) => result,
+            Err(ScriptError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Ok(report(executor, labels, RunOutcome::InputExhausted));
+            },
+            Err(e) => return Err(e),
+        };
+
+        match result {
+            ExecutionResult::Exit => return Ok(report(executor, labels, RunOutcome::Exit)),
+            ExecutionResult::Finished => return Ok(report(executor, labels, RunOutcome::Finished)),
+            ExecutionResult::WaitForInput => {
+                match executor.backend.read_event() {
+                    Ok(Event::Key(KeyEvent { code: KeyCode::Esc, .. })) => {
+                        return Ok(report(executor, labels, RunOutcome::Exit));
+                    },
+                    Ok(_) => {},
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                        return Ok(report(executor, labels, RunOutcome::InputExhausted));
+                    },
+                    Err(e) => return Err(e.into()),
+                }
+            },
+            ExecutionResult::Continue | ExecutionResult::Jump(_) | ExecutionResult::Repeat => {},
+        }
+
+        if executor.script.is_finished() {
+            return Ok(report(executor, labels, RunOutcome::Finished));
+        }
+    }
+
+    Err(ScriptError::InvalidFormat {
+        message: format!("script did not finish within {} commands", MAX_STEPS),
+    })
+}
+
+fn report(executor: &Executor, labels: Vec<String>, outcome: RunOutcome) -> RunReport {
+    RunReport {
+        labels,
+        results: executor.results.clone(),
+        outcome,
+    }
+}
+
+/// Build a backend that replays the given timed events
+pub fn backend_from_events(events: Vec<(Duration, Event)>) -> MemoryBackend {
+    let mut backend = MemoryBackend::new();
+    for (at, event) in events {
+        backend.push_event_at(at, event);
+    }
+    backend
+}
+
+/// Load and parse a key file
+pub fn load_key_file(path: &str) -> ScriptResult<Vec<(Duration, Event)>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| ScriptError::FileError(format!("Cannot read key file '{}': {}", path, e)))?;
+    parse_key_events(&content)
+}
+
+/// Parse key file content into timed events
+pub fn parse_key_events(content: &str) -> ScriptResult<Vec<(Duration, Event)>> {
+    let mut events = Vec::new();
+    let mut last = Duration::ZERO;
+
+    for (index, line) in content.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim_start();
+
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = |message: &str| ScriptError::InvalidFormat {
+            message: format!("key file line {}: {}", line_number, message),
+        };
+
+        let (time, key) = line.split_once(char::is_whitespace)
+            .ok_or_else(|| invalid("expected '<milliseconds> <key>'"))?;
+        let millis: u64 = time.parse()
+            .map_err(|_| invalid(&format!("invalid timestamp '{}'", time)))?;
+        let at = Duration::from_millis(millis);
+        if at < last {
+            return Err(invalid("timestamps must not decrease"));
+        }
+        last = at;
+
+        for code_and_modifiers in parse_key(key.trim_start()).map_err(|e| invalid(&e))? {
+            let (code, modifiers) = code_and_modifiers;
+            events.push((at, Event::Key(KeyEvent::new(code, modifiers))));
+        }
+    }
+
+    Ok(events)
+}
+
+/// Parse the key part of a key file line
+fn parse_key(key: &str) -> Result<Vec<(KeyCode, KeyModifiers)>, String> {
+    // Quoted strings may contain trailing spaces, so only trim the right
+    // side of other forms
+    if let Some(text) = key.strip_prefix('"') {
+        let text = text.trim_end().strip_suffix('"')
+            .ok_or_else(|| format!("unterminated string {}", key.trim_end()))?;
+        return Ok(text.chars().map(|ch| (KeyCode::Char(ch), KeyModifiers::NONE)).collect());
+    }
+
+    let key = key.trim_end();
+    if let Some(name) = key.strip_prefix('<').and_then(|k| k.strip_suffix('>')) {
+        return parse_named_key(name).map(|key| vec![key]);
+    }
+
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => Ok(vec![(KeyCode::Char(ch), KeyModifiers::NONE)]),
+        _ => Err(format!("unknown key '{}'", key)),
+    }
+}
+
+fn parse_named_key(name: &str) -> Result<(KeyCode, KeyModifiers), String> {
+    let code = match name.to_ascii_lowercase().as_str() {
+        "space" => KeyCode::Char(' '),
+        "enter" | "return" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "esc" | "escape" => KeyCode::Esc,
+        "backspace" | "bs" => KeyCode::Backspace,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        lower => {
+            if let Some(ch) = name.strip_prefix("C-").or_else(|| name.strip_prefix("c-")) {
+                let mut chars = ch.chars();
+                if let (Some(ch), None) = (chars.next(), chars.next()) {
+                    return Ok((KeyCode::Char(ch), KeyModifiers::CONTROL));
+                }
+            }
+            match lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                Some(n) if (1..=12).contains(&n) => KeyCode::F(n),
+                _ => return Err(format!("unknown key '<{}>'", name)),
+            }
+        },
+    };
+    Ok((code, KeyModifiers::NONE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key_events() {
+        let events = parse_key_events(r#"
+# comment
+0    <Space>
+100  a
+250  "b c"
+300  <C-r>
+400  <F12>
+"#).unwrap();
+
+        assert_eq!(events.len(), 7);
+        assert_eq!(events[0].1, Event::Key(KeyEvent::new(KeyCode::Char(' '), KeyModifiers::NONE)));
+        assert_eq!(events[1].0, Duration::from_millis(100));
+        assert_eq!(events[3].1, Event::Key(KeyEvent::new(KeyCode::Char(' '), KeyModifiers::NONE)));
+        assert_eq!(events[3].0, Duration::from_millis(250));
+        assert_eq!(events[5].1, Event::Key(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL)));
+        assert_eq!(events[6].1, Event::Key(KeyEvent::new(KeyCode::F(12), KeyModifiers::NONE)));
+    }
+
+    #[test]
+    fn test_parse_key_events_errors() {
+        assert!(parse_key_events("abc x").is_err());
+        assert!(parse_key_events("10 <Nope>").is_err());
+        assert!(parse_key_events("10 ab").is_err());
+        assert!(parse_key_events("20 a\n10 b").is_err());
+        assert!(parse_key_events("10 \"open").is_err());
+    }
+}
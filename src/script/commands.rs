@@ -3,10 +3,16 @@
 //! Defines all the lesson script commands supported by GNU Typist,
 //! matching the C implementation in script.h
 
+use std::fmt;
 use serde::{Deserialize, Serialize};
+use crate::performance::ExerciseResult;
 
 /// All supported script commands
+///
+/// Serialized as a table tagged with the snake_case command name, as used
+/// by JSON and TOML lesson files.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum Command {
     /// Comment line - ignored during execution
     Comment { content: String },
@@ -21,7 +27,10 @@ pub enum Command {
     Instruction { text: String },
     
     /// Clear screen and set banner (B:banner_text)
-    Clear { banner: Option<String> },
+    Clear {
+        #[serde(default)]
+        banner: Option<String>,
+    },
     
     /// Jump to label (G:LABEL_NAME)
     Goto { label: String },
@@ -29,6 +38,12 @@ pub enum Command {
     /// Exit script (X:)
     Exit,
     
+    /// Call a subroutine at a label, returning here on R: (C:LABEL_NAME)
+    Call { label: String },
+    
+    /// Return from the most recent C: call (R:)
+    Return,
+    
     /// Query/question (Q:question_text)
     Query { text: String },
     
@@ -38,15 +53,23 @@ pub enum Command {
     /// Conditional goto if no (N:LABEL_NAME)  
     NoGoto { label: String },
     
+    /// Conditional goto on the last exercise's result (J:wpm < 30:LABEL_NAME)
+    PerformanceGoto {
+        condition: Condition,
+        label: String,
+    },
+    
     /// Drill exercise (D:text_to_type)
     Drill { 
         text: String,
+        #[serde(default)]
         practice_only: bool,
     },
     
     /// Speed test exercise (S:text_to_type)
     SpeedTest { 
         text: String,
+        #[serde(default)]
         practice_only: bool,
     },
     
@@ -56,31 +79,71 @@ pub enum Command {
     /// Drill exercise from file (f:filename.txt)
     DrillFile { 
         path: String,
+        #[serde(default)]
         practice_only: bool,
     },
     
     /// Speed test exercise from file (z:filename.txt)
     SpeedTestFile { 
         path: String,
+        #[serde(default)]
         practice_only: bool,
     },
     
-    /// Key binding (K:key_sequence)
-    KeyBind { sequence: String },
+    /// Bind function key F<key> to a label (K:12:LABEL); NULL unbinds it
+    KeyBind { 
+        key: u8,
+        #[serde(default)]
+        label: Option<String>,
+    },
     
-    /// Set maximum error percentage (E:percentage)
-    ErrorMaxSet { percentage: f32 },
+    /// Set maximum error percentage (E:3%, E:3%* or E:default)
+    ///
+    /// Applies to the next exercise only, unless `persistent` (a trailing
+    /// `*`) keeps it for all later ones. `None` restores the default.
+    ErrorMaxSet { 
+        percentage: Option<f32>,
+        #[serde(default)]
+        persistent: bool,
+    },
     
-    /// Set failure label (F:LABEL_NAME)
-    OnFailureSet { label: String },
+    /// Set failure label (F:LABEL_NAME, F:LABEL_NAME* or F:NULL)
+    ///
+    /// Applies to the next exercise only, unless `persistent` (a trailing
+    /// `*`) keeps it for all later ones. `None` clears it.
+    OnFailureSet { 
+        label: Option<String>,
+        #[serde(default)]
+        persistent: bool,
+    },
     
-    /// Menu definition (M:title)
+    /// Switch typing options (O:word-processor, O:no-word-processor*)
+    ///
+    /// Applies to the next exercise only, unless `persistent` (a trailing
+    /// `*`) keeps it for all later ones.
+    OptionSet {
+        options: Vec<OptionSetting>,
+        #[serde(default)]
+        persistent: bool,
+    },
+    
+    /// Menu definition (M: "title" or M: UP=LABEL "title")
+    ///
+    /// `up` is where Escape leaves to: a parent menu's label, or
+    /// [`MENU_EXIT_LABEL`] to quit. Without it Escape returns to the menu
+    /// shown before this one.
     Menu { 
         title: String,
+        #[serde(default)]
+        up: Option<String>,
+        #[serde(default)]
         items: Vec<MenuItem>,
     },
 }
 
+/// `UP=` target that makes Escape leave the program
+pub const MENU_EXIT_LABEL: &str = "_EXIT";
+
 /// Menu item definition
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MenuItem {
@@ -88,6 +151,178 @@ pub struct MenuItem {
     pub description: String,
 }
 
+/// Way of typing exercises that O: and the command line can switch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TypingOption {
+    /// Fold whitespace and wrap text like a word processor
+    WordProcessor,
+    /// Recover from dropped and doubled characters
+    SyncAhead,
+    /// Hold the cursor until the right key is pressed
+    StopOnError,
+    /// Mark wrong characters in reverse video
+    ShowErrors,
+}
+
+impl TypingOption {
+    pub const ALL: [TypingOption; 4] = [
+        TypingOption::WordProcessor,
+        TypingOption::SyncAhead,
+        TypingOption::StopOnError,
+        TypingOption::ShowErrors,
+    ];
+    
+    /// Name used in O: commands, which is also the long command line option
+    pub fn name(self) -> &'static str {
+        match self {
+            TypingOption::WordProcessor => "word-processor",
+            TypingOption::SyncAhead => "sync-ahead",
+            TypingOption::StopOnError => "stop-on-error",
+            TypingOption::ShowErrors => "show-errors",
+        }
+    }
+    
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|option| option.name() == name)
+    }
+}
+
+/// One option turned on or off by an O: command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OptionSetting {
+    pub option: TypingOption,
+    pub enabled: bool,
+}
+
+impl OptionSetting {
+    /// Parse `name` or `no-name`
+    pub fn parse(text: &str) -> Option<Self> {
+        match TypingOption::from_name(text) {
+            Some(option) => Some(OptionSetting { option, enabled: true }),
+            None => {
+                let option = TypingOption::from_name(text.strip_prefix("no-")?)?;
+                Some(OptionSetting { option, enabled: false })
+            },
+        }
+    }
+}
+
+impl fmt::Display for OptionSetting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = if self.enabled { "" } else { "no-" };
+        write!(f, "{}{}", prefix, self.option.name())
+    }
+}
+
+/// Exercise measurement tested by a J: command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    /// Words per minute
+    Wpm,
+    /// Correct characters per minute
+    Cpm,
+    /// Accuracy percentage (0-100)
+    Accuracy,
+    /// Number of mistyped characters
+    Errors,
+}
+
+/// Comparison operator in a J: condition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Comparison {
+    #[serde(rename = "<")]
+    Less,
+    #[serde(rename = "<=")]
+    LessOrEqual,
+    #[serde(rename = ">")]
+    Greater,
+    #[serde(rename = ">=")]
+    GreaterOrEqual,
+    #[serde(rename = "==")]
+    Equal,
+    #[serde(rename = "!=")]
+    NotEqual,
+}
+
+/// Test against an exercise result, such as `wpm < 30`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Condition {
+    pub metric: Metric,
+    pub comparison: Comparison,
+    pub value: f32,
+}
+
+impl Condition {
+    /// Parse `<metric> <operator> <value>`; spaces are optional and the
+    /// value may end in `%`
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let metric_end = text.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(text.len());
+        let metric = match text[..metric_end].to_ascii_lowercase().as_str() {
+            "wpm" => Metric::Wpm,
+            "cpm" => Metric::Cpm,
+            "accuracy" => Metric::Accuracy,
+            "errors" => Metric::Errors,
+            _ => return None,
+        };
+        
+        let rest = text[metric_end..].trim_start();
+        let operator_end = rest.find(|c: char| !"<>=!".contains(c)).unwrap_or(rest.len());
+        let comparison = match &rest[..operator_end] {
+            "<" => Comparison::Less,
+            "<=" => Comparison::LessOrEqual,
+            ">" => Comparison::Greater,
+            ">=" => Comparison::GreaterOrEqual,
+            "==" | "=" => Comparison::Equal,
+            "!=" => Comparison::NotEqual,
+            _ => return None,
+        };
+        
+        let value = rest[operator_end..].trim().trim_end_matches('%').trim().parse::<f32>().ok()?;
+        Some(Condition { metric, comparison, value })
+    }
+    
+    /// Whether `result` satisfies the condition
+    pub fn holds(&self, result: &ExerciseResult) -> bool {
+        let actual = match self.metric {
+            Metric::Wpm => result.wpm,
+            Metric::Cpm => result.cpm(),
+            Metric::Accuracy => result.accuracy(),
+            Metric::Errors => result.errors as f32,
+        };
+        match self.comparison {
+            Comparison::Less => actual < self.value,
+            Comparison::LessOrEqual => actual <= self.value,
+            Comparison::Greater => actual > self.value,
+            Comparison::GreaterOrEqual => actual >= self.value,
+            Comparison::Equal => actual == self.value,
+            Comparison::NotEqual => actual != self.value,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let metric = match self.metric {
+            Metric::Wpm => "wpm",
+            Metric::Cpm => "cpm",
+            Metric::Accuracy => "accuracy",
+            Metric::Errors => "errors",
+        };
+        let operator = match self.comparison {
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+        };
+        write!(f, "{} {} {}", metric, operator, self.value)
+    }
+}
+
 /// Command character constants matching C implementation
 pub mod chars {
     pub const COMMENT: char = '#';
@@ -100,9 +335,12 @@ pub mod chars {
     pub const CLEAR: char = 'B';
     pub const GOTO: char = 'G';
     pub const EXIT: char = 'X';
+    pub const CALL: char = 'C';
+    pub const RETURN: char = 'R';
     pub const QUERY: char = 'Q';
     pub const YES_GOTO: char = 'Y';
     pub const NO_GOTO: char = 'N';
+    pub const PERFORMANCE_GOTO: char = 'J';
     pub const DRILL: char = 'D';
     pub const DRILL_PRACTICE_ONLY: char = 'd';
     pub const SPEEDTEST: char = 'S';
@@ -115,7 +353,10 @@ pub mod chars {
     pub const KEYBIND: char = 'K';
     pub const ERROR_MAX_SET: char = 'E';
     pub const ON_FAILURE_SET: char = 'F';
+    pub const OPTION_SET: char = 'O';
     pub const MENU: char = 'M';
+    /// Splices another script file in at parse time (@:file.typ[:NAMESPACE])
+    pub const INCLUDE: char = '@';
 }
 
 impl Command {
@@ -172,6 +413,10 @@ impl Command {
                 label: data.to_string() 
             },
             chars::EXIT => Command::Exit,
+            chars::CALL => Command::Call { 
+                label: data.to_string() 
+            },
+            chars::RETURN => Command::Return,
             chars::QUERY => Command::Query { 
                 text: data.to_string() 
             },
@@ -181,6 +426,23 @@ impl Command {
             chars::NO_GOTO => Command::NoGoto { 
                 label: data.to_string() 
             },
+            chars::PERFORMANCE_GOTO => {
+                // Format: "<condition>:<label>"
+                let invalid = || crate::script::ScriptError::InvalidCommand {
+                    line: line_number,
+                    content: line.to_string(),
+                };
+                let (condition, label) = data.split_once(chars::SEP).ok_or_else(invalid)?;
+                let condition = Condition::parse(condition).ok_or_else(invalid)?;
+                let label = label.trim();
+                if label.is_empty() {
+                    return Err(invalid());
+                }
+                Command::PerformanceGoto { 
+                    condition,
+                    label: label.to_string(),
+                }
+            },
             chars::DRILL => Command::Drill { 
                 text: data.to_string(),
                 practice_only: false,
@@ -216,34 +478,85 @@ impl Command {
                 path: data.to_string(),
                 practice_only: true,
             },
-            chars::KEYBIND => Command::KeyBind { 
-                sequence: data.to_string() 
+            chars::KEYBIND => {
+                // Format: "<function key number>:<label>"
+                let invalid = || crate::script::ScriptError::InvalidCommand {
+                    line: line_number,
+                    content: line.to_string(),
+                };
+                let (key_str, label) = data.split_once(chars::SEP).ok_or_else(invalid)?;
+                let key = key_str.trim().parse::<u8>().map_err(|_| invalid())?;
+                if !(1..=12).contains(&key) {
+                    return Err(invalid());
+                }
+                let label = label.trim();
+                if label.is_empty() {
+                    return Err(invalid());
+                }
+                Command::KeyBind { 
+                    key,
+                    label: if label == "NULL" { None } else { Some(label.to_string()) },
+                }
             },
             chars::ERROR_MAX_SET => {
-                // Handle special cases like "default"
-                if data.trim() == "default" {
-                    Command::ErrorMaxSet { percentage: 0.0 }
+                // Format: "<value>%", "default", either optionally followed by "*"
+                let (value, persistent) = persistence_marker(data);
+                if value.eq_ignore_ascii_case("default") {
+                    Command::ErrorMaxSet { percentage: None, persistent }
                 } else {
-                    // Remove % sign and any trailing characters, parse as float
-                    let percentage_str = data.trim()
-                        .trim_end_matches('*')
-                        .trim_end_matches('%')
-                        .trim();
-                    let percentage = percentage_str.parse::<f32>().map_err(|_| {
+                    let percentage = value.trim_end_matches('%').trim().parse::<f32>().map_err(|_| {
                         crate::script::ScriptError::InvalidCommand {
                             line: line_number,
                             content: line.to_string(),
                         }
                     })?;
-                    Command::ErrorMaxSet { percentage }
+                    Command::ErrorMaxSet { percentage: Some(percentage), persistent }
                 }
             },
-            chars::ON_FAILURE_SET => Command::OnFailureSet { 
-                label: data.to_string() 
+            chars::ON_FAILURE_SET => {
+                // Format: "<label>" or "NULL", optionally followed by "*"
+                let (label, persistent) = persistence_marker(data);
+                if label.is_empty() {
+                    return Err(crate::script::ScriptError::InvalidCommand {
+                        line: line_number,
+                        content: line.to_string(),
+                    });
+                }
+                Command::OnFailureSet { 
+                    label: if label == "NULL" { None } else { Some(label.to_string()) },
+                    persistent,
+                }
+            },
+            chars::OPTION_SET => {
+                // Format: "[no-]<option> ...", optionally followed by "*"
+                let (settings, persistent) = persistence_marker(data);
+                let options = settings.split_whitespace()
+                    .map(OptionSetting::parse)
+                    .collect::<Option<Vec<_>>>()
+                    .filter(|options| !options.is_empty())
+                    .ok_or_else(|| crate::script::ScriptError::InvalidCommand {
+                        line: line_number,
+                        content: line.to_string(),
+                    })?;
+                Command::OptionSet { options, persistent }
             },
             chars::MENU => {
+                // Format: [UP=LABEL] "title"
+                let mut cleaned_data = data.trim();
+                let mut up = None;
+                if let Some(rest) = cleaned_data.strip_prefix("UP=") {
+                    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                    if end == 0 {
+                        return Err(crate::script::ScriptError::InvalidCommand {
+                            line: line_number,
+                            content: line.to_string(),
+                        });
+                    }
+                    up = Some(rest[..end].to_string());
+                    cleaned_data = rest[end..].trim_start();
+                }
+                
                 // Remove quotes and leading/trailing spaces from menu title
-                let cleaned_data = data.trim();
                 let title = if cleaned_data.starts_with('"') && cleaned_data.ends_with('"') && cleaned_data.len() >= 2 {
                     cleaned_data[1..cleaned_data.len()-1].to_string()
                 } else {
@@ -251,6 +564,7 @@ impl Command {
                 };
                 Command::Menu { 
                     title,
+                    up,
                     items: Vec::new(), // Will be populated by parser
                 }
             },
@@ -264,4 +578,32 @@ impl Command {
         
         Ok(Some(command))
     }
-}
\ No newline at end of file
+    
+    /// Every label name this command defines or jumps to
+    pub fn labels_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            Command::Label { name } => vec![name],
+            Command::Goto { label } |
+            Command::Call { label } |
+            Command::YesGoto { label } |
+            Command::NoGoto { label } |
+            Command::PerformanceGoto { label, .. } |
+            Command::OnFailureSet { label: Some(label), .. } |
+            Command::KeyBind { label: Some(label), .. } => vec![label],
+            Command::Menu { up, items, .. } => up.iter_mut()
+                .filter(|up| up.as_str() != MENU_EXIT_LABEL)
+                .chain(items.iter_mut().map(|item| &mut item.label))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Split a trailing `*` persistence marker off E:, F: and O: data
+fn persistence_marker(data: &str) -> (&str, bool) {
+    let data = data.trim();
+    match data.strip_suffix('*') {
+        Some(value) => (value.trim_end(), true),
+        None => (data, false),
+    }
+}
@@ -0,0 +1,182 @@
+//! Lesson file encodings
+//!
+//! Lessons are parsed as UTF-8, but older and community lessons are often
+//! stored in a legacy code page. The encoding of a file is taken from, in
+//! order:
+//!
+//! 1. a byte order mark,
+//! 2. a `#@charset: NAME` line among the comments at the top of the file
+//!    (any WHATWG label, e.g. `koi8-r`, `windows-1251`, `iso-8859-2`),
+//! 3. the content itself: valid UTF-8 is taken as UTF-8, anything else
+//!    is decoded with whichever of KOI8-R, windows-1251, windows-1252
+//!    (Latin-1) and ISO-8859-2 (Latin-2) gives the most plausible words.
+
+use encoding_rs::{Encoding, ISO_8859_2, KOI8_R, UTF_8, WINDOWS_1251, WINDOWS_1252};
+use crate::script::{ScriptError, ScriptResult};
+
+/// Code pages tried when a file is not UTF-8 and does not say what it is,
+/// in order of preference when they score the same
+const LEGACY_ENCODINGS: [&Encoding; 4] = [WINDOWS_1252, ISO_8859_2, KOI8_R, WINDOWS_1251];
+
+/// Decode a lesson file's bytes to text, returning the encoding used
+pub fn decode_lesson(bytes: &[u8], path: &str) -> ScriptResult<(String, &'static Encoding)> {
+    let error = |message: String| ScriptError::Encoding { path: path.to_string(), message };
+
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        return decode_strict(&bytes[bom_length..], encoding).ok_or_else(|| {
+            error(format!("invalid {} text after its byte order mark", encoding.name()))
+        });
+    }
+
+    if let Some(label) = charset_directive(bytes) {
+        let encoding = Encoding::for_label_no_replacement(label)
+            .ok_or_else(|| error(format!("unknown charset '{}'", String::from_utf8_lossy(label))))?;
+        return decode_strict(bytes, encoding)
+            .ok_or_else(|| error(format!("invalid {} text", encoding.name())));
+    }
+
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return Ok((text.to_string(), UTF_8));
+    }
+
+    LEGACY_ENCODINGS.iter()
+        .map(|&encoding| {
+            let text = encoding.decode_without_bom_handling(bytes).0.into_owned();
+            (plausibility(&text), text, encoding)
+        })
+        .filter(|(score, ..)| *score > 0)
+        // max_by_key keeps the last of equal scores, so go backwards
+        .rev()
+        .max_by_key(|(score, ..)| *score)
+        .map(|(_, text, encoding)| (text, encoding))
+        .ok_or_else(|| {
+            let tried: Vec<&str> = LEGACY_ENCODINGS.iter().map(|e| e.name()).collect();
+            error(format!("not UTF-8, and does not look like {}; add a '#@charset: NAME' line",
+                tried.join(", ")))
+        })
+}
+
+fn decode_strict(bytes: &[u8], encoding: &'static Encoding) -> Option<(String, &'static Encoding)> {
+    encoding.decode_without_bom_handling_and_without_replacement(bytes)
+        .map(|text| (text.into_owned(), encoding))
+}
+
+/// Whether a comment is a `#@charset:` directive
+pub fn is_charset_directive(comment: &str) -> bool {
+    charset_directive(comment.as_bytes()).is_some()
+}
+
+/// Label from a `#@charset:` line in the leading comment block
+///
+/// Works on raw bytes, since the file can't be decoded yet. Labels are
+/// ASCII in every encoding this supports.
+fn charset_directive(bytes: &[u8]) -> Option<&[u8]> {
+    bytes.split(|&b| b == b'\n')
+        .map(|line| line.trim_ascii())
+        .take_while(|line| line.is_empty() || line.starts_with(b"#"))
+        .find_map(|line| {
+            let directive = line.strip_prefix(b"#@")?;
+            let colon = directive.iter().position(|&b| b == b':')?;
+            directive[..colon].trim_ascii().eq_ignore_ascii_case(b"charset")
+                .then(|| directive[colon + 1..].trim_ascii())
+        })
+        .filter(|label| !label.is_empty())
+}
+
+/// How much decoded text looks like real words
+///
+/// Words that mix Latin and Cyrillic letters, or switch from lower to
+/// upper case partway through, are what a wrong code page produces, and
+/// Latin words made only of accented letters are usually Cyrillic read
+/// as Latin-1. Control characters and stray symbols count against the
+/// text too.
+fn plausibility(text: &str) -> i64 {
+    let mut score = 0;
+
+    for c in text.chars() {
+        if (c.is_control() && !c.is_ascii_whitespace()) || (!c.is_ascii() && !c.is_alphanumeric() && !c.is_whitespace()) {
+            score -= 1;
+        }
+    }
+
+    for word in text.split(|c: char| !c.is_alphabetic()).filter(|w| !w.is_ascii()) {
+        let cyrillic = word.chars().any(|c| ('\u{400}'..='\u{4ff}').contains(&c));
+        let latin = word.chars().any(|c| c.is_ascii_alphabetic());
+        let case_flip = word.chars().zip(word.chars().skip(1)).any(|(a, b)| a.is_lowercase() && b.is_uppercase());
+        let upper = word.chars().filter(|c| c.is_uppercase()).count();
+        let lower = word.chars().filter(|c| c.is_lowercase()).count();
+
+        score += if cyrillic && latin {
+            -2
+        } else if case_flip {
+            -1
+        } else if upper > lower || (!cyrillic && !latin && word.chars().count() > 1) {
+            0
+        } else {
+            1
+        };
+    }
+
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(text: &str, encoding: &'static Encoding) -> Vec<u8> {
+        let (bytes, _, unmappable) = encoding.encode(text);
+        assert!(!unmappable);
+        bytes.into_owned()
+    }
+
+    fn detect(bytes: &[u8]) -> (String, &'static str) {
+        let (text, encoding) = decode_lesson(bytes, "lesson.typ").unwrap();
+        (text, encoding.name())
+    }
+
+    #[test]
+    fn test_utf8_and_bom() {
+        assert_eq!(detect("T:Grüße\n".as_bytes()), ("T:Grüße\n".to_string(), "UTF-8"));
+        assert_eq!(detect(b"\xef\xbb\xbfT:x\n"), ("T:x\n".to_string(), "UTF-8"));
+        assert_eq!(detect(b"\xff\xfeT\x00:\x00"), ("T:".to_string(), "UTF-16LE"));
+    }
+
+    #[test]
+    fn test_charset_directive() {
+        let mut bytes = b"# Lesson\n#@ Charset : koi8-r\n".to_vec();
+        bytes.extend(encode("T:Привет\n", KOI8_R));
+        let (text, encoding) = detect(&bytes);
+        assert_eq!(encoding, "KOI8-R");
+        assert!(text.ends_with("T:Привет\n"));
+
+        // Only the header counts
+        let mut bytes = b"T:x\n#@charset: koi8-r\n".to_vec();
+        bytes.extend(encode("D:Привет\n", WINDOWS_1251));
+        assert_eq!(detect(&bytes).1, "windows-1251");
+
+        let error = decode_lesson(b"#@charset: klingon\nX:\n", "a.typ").unwrap_err();
+        assert_eq!(error.to_string(), "a.typ: unknown charset 'klingon'");
+    }
+
+    #[test]
+    fn test_detects_legacy_code_pages() {
+        let samples = [
+            ("D:съешь же ещё этих мягких французских булок, да выпей чаю\n", KOI8_R),
+            ("D:съешь же ещё этих мягких французских булок, да выпей чаю\n", WINDOWS_1251),
+            ("D:Příliš žluťoučký kůň úpěl ďábelské ódy\n", ISO_8859_2),
+            ("D:Voix ambiguë d'un cœur qui au zéphyr préfère les jattes de kiwis\n", WINDOWS_1252),
+            ("D:Falsches Üben von Xylophonmusik quält jeden größeren Zwerg\n", WINDOWS_1252),
+        ];
+        for (text, encoding) in samples {
+            assert_eq!(detect(&encode(text, encoding)), (text.to_string(), encoding.name()));
+        }
+    }
+
+    #[test]
+    fn test_undetectable_input_names_encodings_tried() {
+        let error = decode_lesson(b"\x81\x8d\x8f\x90\x9d", "b.typ").unwrap_err();
+        assert!(matches!(error, ScriptError::Encoding { .. }));
+        assert!(error.to_string().contains("KOI8-R, windows-1251"), "{}", error);
+    }
+}
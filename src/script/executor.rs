@@ -2,112 +2,242 @@
 //! 
 //! Handles execution of parsed script commands with integrated exercise engine.
 
-use crate::script::{Script, ScriptResult, load_text_file};
-use crate::script::commands::Command;
+use crate::script::{Script, ScriptError, ScriptResult, load_text_file};
+use crate::script::commands::{Command, MENU_EXIT_LABEL};
+use crate::script::state::{self, ExecutorState};
+use crate::script::trace::{ExerciseKind, JumpCause, TraceEvent, TraceSink};
 use crate::exercises::{TutorialExercise, DrillExercise, SpeedTestExercise, ExerciseOutcome};
 use crate::menu::Menu;
-use crossterm::{
-    execute,
-    terminal::{Clear, ClearType},
-    cursor,
-};
-use std::io::{stdout, Write};
+use crate::performance::ExerciseResult;
+use crate::typing::TypingOptions;
+use crate::terminal::{Backend, CrosstermBackend, write_centered, write_line, write_wrapped_text};
+use crossterm::event::{Event, KeyCode, KeyEvent};
+use std::collections::HashMap;
+use std::io;
 
-/// Helper function to center text in terminal
-fn center_text(text: &str) -> String {
-    // Try to get terminal size, fall back to reasonable defaults
-    let width = match crossterm::terminal::size() {
-        Ok((w, _)) => w as usize,
-        Err(_) => {
-            // If we can't get terminal size, try environment variables or use 80
-            std::env::var("COLUMNS")
-                .ok()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(80)
-        }
-    };
-    
-    let text_len = text.chars().count(); // Use char count for proper Unicode handling
-    if text_len >= width {
-        return text.to_string(); // Don't try to center if text is too long
+/// Convert a failed exercise or menu into a script error
+///
+/// These only fail when the backend can no longer read input or write
+/// output, which should stop execution rather than skip the exercise.
+fn exercise_error(error: Box<dyn std::error::Error>) -> ScriptError {
+    match error.downcast::<io::Error>() {
+        Ok(io_error) => ScriptError::Io(*io_error),
+        Err(other) => ScriptError::Io(io::Error::other(other.to_string())),
     }
-    
-    let padding = (width - text_len) / 2;
-    format!("{}{}", " ".repeat(padding), text)
 }
 
-/// Get appropriate line width for text content (smaller than terminal width)
-fn get_content_width() -> usize {
-    match crossterm::terminal::size() {
-        Ok((w, _)) => ((w as usize) * 3 / 4).min(60), // Use 75% of terminal width, max 60 chars
-        Err(_) => 60, // Default to 60 characters
-    }
-}
+/// Deepest allowed nesting of C: calls, so runaway recursion in a lesson
+/// stops with an error instead of growing without bound
+pub const MAX_CALL_DEPTH: usize = 64;
 
-/// Print text with proper word wrapping and cursor positioning
-fn print_wrapped_text(text: &str) {
-    const LINE_WIDTH: usize = 50;
-    
-    // Split text into words and wrap them
-    let words: Vec<&str> = text.split_whitespace().collect();
-    let mut current_line = String::new();
-    
-    for word in words {
-        // If adding this word would exceed the line width, print current line and start new one
-        if !current_line.is_empty() && current_line.len() + 1 + word.len() > LINE_WIDTH {
-            // Force cursor to column 1 and print the line
-            print!("\x1B[1G{}\n", current_line);
-            current_line.clear();
-        }
-        
-        // Add word to current line
-        if !current_line.is_empty() {
-            current_line.push(' ');
-        }
-        current_line.push_str(word);
-    }
-    
-    // Print any remaining text
-    if !current_line.is_empty() {
-        print!("\x1B[1G{}\n", current_line);
-    }
-}
+/// Error percentage drills allow when neither E: nor `--max-error` says otherwise
+pub const DEFAULT_MAX_ERROR: f32 = 3.0;
 
 /// Script executor state
 pub struct Executor {
     pub script: Script,
+    /// Error percentage allowed in drills, as set by `E:...*`
     pub error_percentage: f32,
+    /// Value `E:default` goes back to
+    pub default_error_percentage: f32,
+    /// Upper bound from an explicit `--max-error`; E: can only be stricter
+    pub error_percentage_cap: Option<f32>,
+    /// One-shot E: value for the next drill or speed test only
+    pub next_error_percentage: Option<f32>,
+    /// Label jumped to when a drill fails, as set by `F:...*`
     pub failure_label: Option<String>,
+    /// One-shot F: label for the next drill or speed test only;
+    /// `Some(None)` is a one-shot `F:NULL`
+    pub next_failure_label: Option<Option<String>>,
+    /// Typing options for drills and speed tests, as set by `O:...*`
+    pub typing_options: TypingOptions,
+    /// One-shot O: options for the next drill or speed test only
+    pub next_typing_options: Option<TypingOptions>,
     pub last_query_response: Option<bool>, // Track Y/N responses for conditional jumps
+    /// Results of every drill and speed test finished so far, failed ones
+    /// included, in order
+    pub results: Vec<ExerciseResult>,
+    /// Function key bindings set by K: (key number -> label)
+    pub key_bindings: HashMap<u8, String>,
+    /// Positions of the C: commands awaiting an R:, innermost last
+    pub call_stack: Vec<usize>,
+    /// Positions of the menus shown so far, current menu last; Escape
+    /// without an UP= target goes back one entry
+    pub menu_stack: Vec<usize>,
+    /// Terminal used for all exercises, menus and prompts
+    pub backend: Box<dyn Backend>,
+    /// Where to log commands, jumps and outcomes, if anywhere
+    pub trace: Option<TraceSink>,
+    /// Why the command being executed jumped, when the command alone doesn't say
+    jump_cause: Option<JumpCause>,
 }
 
 impl Executor {
-    /// Create a new executor for a script
+    /// Create a new executor for a script, attached to the real terminal
     pub fn new(script: Script) -> Self {
+        Self::with_backend(script, Box::new(CrosstermBackend::new()))
+    }
+    
+    /// Create a new executor that talks to the user through `backend`
+    pub fn with_backend(script: Script, backend: Box<dyn Backend>) -> Self {
         Self {
             script,
-            error_percentage: 0.0,
+            error_percentage: DEFAULT_MAX_ERROR,
+            default_error_percentage: DEFAULT_MAX_ERROR,
+            error_percentage_cap: None,
+            next_error_percentage: None,
             failure_label: None,
+            next_failure_label: None,
+            typing_options: TypingOptions::default(),
+            next_typing_options: None,
             last_query_response: None,
+            results: Vec::new(),
+            key_bindings: HashMap::new(),
+            call_stack: Vec::new(),
+            menu_stack: Vec::new(),
+            backend,
+            trace: None,
+            jump_cause: None,
+        }
+    }
+    
+    /// Log execution to `sink`
+    pub fn with_trace(mut self, sink: TraceSink) -> Self {
+        self.trace = Some(sink);
+        self
+    }
+    
+    /// Use an explicitly requested error limit (`--max-error`)
+    ///
+    /// This becomes the value `E:default` resets to, and E: commands in
+    /// the script can only lower it from here on. A stricter E: already
+    /// in force, as in a resumed session, is kept.
+    pub fn set_max_error(&mut self, percentage: f32) {
+        if self.error_percentage == self.default_error_percentage {
+            self.error_percentage = percentage;
+        } else {
+            self.error_percentage = self.error_percentage.min(percentage);
+        }
+        self.next_error_percentage = self.next_error_percentage.map(|next| next.min(percentage));
+        self.default_error_percentage = percentage;
+        self.error_percentage_cap = Some(percentage);
+    }
+    
+    /// Snapshot of everything but the script and backend, for resuming later
+    pub fn state(&self) -> ScriptResult<ExecutorState> {
+        Ok(ExecutorState {
+            lesson: state::lesson_key(&self.script.path),
+            content_hash: state::content_hash(&self.script)?,
+            position: self.script.position,
+            error_percentage: self.error_percentage,
+            default_error_percentage: self.default_error_percentage,
+            error_percentage_cap: self.error_percentage_cap,
+            next_error_percentage: self.next_error_percentage,
+            failure_label: self.failure_label.clone(),
+            next_failure_label: self.next_failure_label.clone().flatten(),
+            next_failure_cleared: self.next_failure_label == Some(None),
+            typing_options: self.typing_options,
+            next_typing_options: self.next_typing_options,
+            last_query_response: self.last_query_response,
+            results: self.results.clone(),
+            key_bindings: self.key_bindings.clone(),
+            call_stack: self.call_stack.clone(),
+            menu_stack: self.menu_stack.clone(),
+        })
+    }
+    
+    /// Continue from a saved state
+    ///
+    /// Fails, leaving the executor untouched, if the state belongs to
+    /// another lesson or the lesson has changed since it was saved.
+    pub fn resume(&mut self, state: ExecutorState) -> ScriptResult<()> {
+        let mismatch = |message: &str| ScriptError::InvalidFormat {
+            message: format!("cannot resume {}: {}", self.script.path, message),
+        };
+        if state.lesson != state::lesson_key(&self.script.path) {
+            return Err(mismatch("saved state is for another lesson"));
+        }
+        if state.content_hash != state::content_hash(&self.script)? {
+            return Err(mismatch("lesson has changed since it was saved"));
         }
+        let length = self.script.commands.len();
+        if state.position > length || state.call_stack.iter().chain(&state.menu_stack).any(|&p| p >= length) {
+            return Err(mismatch("saved position is outside the lesson"));
+        }
+        
+        self.script.position = state.position;
+        self.error_percentage = state.error_percentage;
+        self.default_error_percentage = state.default_error_percentage;
+        self.error_percentage_cap = state.error_percentage_cap;
+        self.next_error_percentage = state.next_error_percentage;
+        self.failure_label = state.failure_label;
+        self.next_failure_label = match (state.next_failure_label, state.next_failure_cleared) {
+            (None, true) => Some(None),
+            (label, _) => label.map(Some),
+        };
+        self.typing_options = state.typing_options;
+        self.next_typing_options = state.next_typing_options;
+        self.last_query_response = state.last_query_response;
+        self.results = state.results;
+        self.key_bindings = state.key_bindings;
+        self.call_stack = state.call_stack;
+        self.menu_stack = state.menu_stack;
+        Ok(())
+    }
+    
+    /// Result of the most recently completed drill or speed test
+    pub fn last_result(&self) -> Option<&ExerciseResult> {
+        self.results.last()
     }
     
     /// Execute the next command in the script
     pub fn execute_next(&mut self) -> ScriptResult<ExecutionResult> {
-        if let Some(command) = self.script.current_command() {
+        if let Some(command) = self.script.current_command().cloned() {
+            let position = self.script.position;
+            self.trace(|executor| TraceEvent::Command {
+                index: position,
+                span: executor.script.span(position).cloned(),
+                command: command.clone(),
+            })?;
+            self.jump_cause = None;
+            // One-shot E:, F: and O: settings are used up by the exercise,
+            // but a repeat of it should see them again
+            let one_shot = (self.next_error_percentage, self.next_failure_label.clone(), self.next_typing_options);
             let result = self.execute_command(command.clone())?;
+            let cause = self.jump_cause.take().or_else(|| JumpCause::of(&command));
             match result {
-                ExecutionResult::Jump(ref label) => {
+                ExecutionResult::Jump(label) => {
                     // Handle jump by updating script position
-                    self.script.goto_label(label)?;
+                    self.script.goto_label_from(&label, position)?;
+                    self.trace(|executor| TraceEvent::Jump {
+                        from: position,
+                        to: executor.script.position,
+                        label: Some(label),
+                        cause: cause.unwrap_or(JumpCause::Goto),
+                    })?;
                     Ok(ExecutionResult::Continue)
                 },
                 _ => {
                     // Normal execution - advance to next command
-                    if !matches!(result, ExecutionResult::Exit | ExecutionResult::Finished) {
+                    if matches!(result, ExecutionResult::Exit | ExecutionResult::Finished) {
+                        return Ok(result);
+                    }
+                    let repeat = matches!(result, ExecutionResult::Repeat);
+                    if !repeat {
                         self.script.next();
+                    } else if self.script.position == position {
+                        (self.next_error_percentage, self.next_failure_label, self.next_typing_options) = one_shot;
+                    }
+                    // R:, going back a menu and repeats move without a label
+                    if let Some(cause) = cause.filter(|_| repeat || self.script.position != position + 1) {
+                        self.trace(|executor| TraceEvent::Jump {
+                            from: position,
+                            to: executor.script.position,
+                            label: None,
+                            cause,
+                        })?;
                     }
-                    Ok(result)
+                    Ok(if repeat { ExecutionResult::Continue } else { result })
                 }
             }
         } else {
@@ -115,6 +245,16 @@ impl Executor {
         }
     }
     
+    /// Record a trace event, if tracing
+    fn trace(&mut self, event: impl FnOnce(&Self) -> TraceEvent) -> ScriptResult<()> {
+        let Some(mut sink) = self.trace.take() else {
+            return Ok(());
+        };
+        let result = sink.record(event(self));
+        self.trace = Some(sink);
+        result
+    }
+    
     /// Execute a specific command
     fn execute_command(&mut self, command: Command) -> ScriptResult<ExecutionResult> {
         match command {
@@ -122,44 +262,35 @@ impl Executor {
             
             Command::Label { .. } => Ok(ExecutionResult::Continue),
             
-            Command::Tutorial { text } => {
-                let exercise = TutorialExercise::new(text);
-                match exercise.execute() {
-                    Ok(ExerciseOutcome::Completed(_)) => Ok(ExecutionResult::Continue),
-                    Ok(ExerciseOutcome::Quit) => Ok(ExecutionResult::Exit),
-                    Ok(ExerciseOutcome::Retry) => Ok(ExecutionResult::Continue), // Retry the same command
-                    Ok(ExerciseOutcome::Failed) => Ok(ExecutionResult::Continue), // Continue for tutorials
-                    Err(_) => Ok(ExecutionResult::Continue), // Handle errors gracefully
-                }
-            },
+            Command::Tutorial { text } => self.run_tutorial(text),
             
             Command::Instruction { text } => {
-                let mut stdout = stdout();
-                print!("\x1B[2J\x1B[1;1H");
+                let backend = self.backend.as_mut();
+                backend.clear_screen()?;
                 
-                println!();
-                println!("{}", center_text("=== INSTRUCTION ==="));
-                println!();
+                write_line(backend, "")?;
+                write_centered(backend, "=== INSTRUCTION ===")?;
+                write_line(backend, "")?;
                 
                 // Print text with proper wrapping, left-justified
-                print_wrapped_text(&text);
+                write_wrapped_text(backend, &text)?;
                 
-                println!();
-                println!("Press any key to continue...");
-                stdout.flush().ok();
+                write_line(backend, "")?;
+                write_line(backend, "Press any key to continue...")?;
+                backend.flush().ok();
                 Ok(ExecutionResult::WaitForInput)
             },
             
             Command::Clear { banner } => {
-                let mut stdout = stdout();
-                print!("\x1B[2J\x1B[1;1H");
+                let backend = self.backend.as_mut();
+                backend.clear_screen()?;
                 
                 if let Some(banner_text) = banner {
-                    println!();
-                    println!("{}", center_text(&format!("=== {} ===", banner_text)));
-                    println!();
+                    write_line(backend, "")?;
+                    write_centered(backend, &format!("=== {} ===", banner_text))?;
+                    write_line(backend, "")?;
                 }
-                stdout.flush().ok();
+                backend.flush().ok();
                 Ok(ExecutionResult::Continue)
             },
             
@@ -169,69 +300,81 @@ impl Executor {
             
             Command::Exit => Ok(ExecutionResult::Exit),
             
-            Command::Drill { text, practice_only } => {
-                let exercise = DrillExercise::new(text, practice_only, self.error_percentage);
-                match exercise.execute() {
-                    Ok(ExerciseOutcome::Completed(_)) => Ok(ExecutionResult::Continue),
-                    Ok(ExerciseOutcome::Quit) => Ok(ExecutionResult::Exit),
-                    Ok(ExerciseOutcome::Retry) => Ok(ExecutionResult::Continue), // Retry the same command
-                    Ok(ExerciseOutcome::Failed) => {
-                        // Jump to failure label if set, otherwise continue
-                        if let Some(ref label) = self.failure_label {
-                            Ok(ExecutionResult::Jump(label.clone()))
-                        } else {
-                            Ok(ExecutionResult::Continue)
-                        }
-                    },
-                    Err(_) => Ok(ExecutionResult::Continue), // Handle errors gracefully
+            Command::Call { label } => {
+                if self.call_stack.len() >= MAX_CALL_DEPTH {
+                    return Err(self.error_here(format!(
+                        "C:{} exceeds the maximum call depth of {}", label, MAX_CALL_DEPTH
+                    )));
                 }
+                self.call_stack.push(self.script.position);
+                Ok(ExecutionResult::Jump(label))
             },
             
-            Command::SpeedTest { text, practice_only } => {
-                let exercise = SpeedTestExercise::new(text, practice_only, None); // No time limit by default
-                match exercise.execute() {
-                    Ok(ExerciseOutcome::Completed(_)) => Ok(ExecutionResult::Continue),
-                    Ok(ExerciseOutcome::Quit) => Ok(ExecutionResult::Exit),
-                    Ok(ExerciseOutcome::Retry) => Ok(ExecutionResult::Continue), // Retry the same command
-                    Ok(ExerciseOutcome::Failed) => Ok(ExecutionResult::Continue), // Speed tests don't typically fail
-                    Err(_) => Ok(ExecutionResult::Continue), // Handle errors gracefully
+            Command::Return => {
+                match self.call_stack.pop() {
+                    // Resume at the C: itself; the normal advance then
+                    // moves past it
+                    Some(call_position) => {
+                        self.script.position = call_position;
+                        Ok(ExecutionResult::Continue)
+                    },
+                    None => Err(self.error_here("R: without a matching C: call".to_string())),
                 }
             },
             
-            Command::ErrorMaxSet { percentage } => {
-                self.error_percentage = percentage;
+            Command::Drill { text, practice_only } => self.run_drill(text, practice_only),
+            
+            Command::SpeedTest { text, practice_only } => self.run_speed_test(text, practice_only),
+            
+            Command::ErrorMaxSet { percentage, persistent } => {
+                let percentage = match (percentage, self.error_percentage_cap) {
+                    (Some(percentage), Some(cap)) => percentage.min(cap),
+                    (Some(percentage), None) => percentage,
+                    (None, _) => self.default_error_percentage,
+                };
+                if persistent {
+                    self.error_percentage = percentage;
+                    self.next_error_percentage = None;
+                } else {
+                    self.next_error_percentage = Some(percentage);
+                }
                 Ok(ExecutionResult::Continue)
             },
             
-            Command::OnFailureSet { label } => {
-                self.failure_label = Some(label);
+            Command::OnFailureSet { label, persistent } => {
+                if persistent {
+                    self.failure_label = label;
+                    self.next_failure_label = None;
+                } else {
+                    self.next_failure_label = Some(label);
+                }
                 Ok(ExecutionResult::Continue)
             },
             
-            Command::Menu { title, items } => {
-                let mut menu = Menu::new(title);
-                for item in items {
-                    menu.add_item(item.label, item.description);
-                }
-                
-                match menu.display() {
-                    Ok(Some(selected_label)) => Ok(ExecutionResult::Jump(selected_label)),
-                    Ok(None) => Ok(ExecutionResult::Exit), // User quit menu
-                    Err(_) => Ok(ExecutionResult::Continue), // Handle errors gracefully
+            Command::OptionSet { options, persistent } => {
+                if persistent {
+                    self.typing_options = self.typing_options.with(&options);
+                    self.next_typing_options = self.next_typing_options.map(|next| next.with(&options));
+                } else {
+                    let next = self.next_typing_options.unwrap_or(self.typing_options);
+                    self.next_typing_options = Some(next.with(&options));
                 }
+                Ok(ExecutionResult::Continue)
             },
             
+            Command::Menu { .. } => self.run_menu(),
+            
             Command::Query { text } => {
-                let mut stdout = stdout();
-                execute!(stdout, Clear(ClearType::All), cursor::MoveTo(0, 0)).ok();
-                println!("{}", text);
-                println!("\nPress Y for yes, N for no, ESC to quit...");
-                stdout.flush().ok();
+                let backend = self.backend.as_mut();
+                backend.clear_screen().ok();
+                write_line(backend, &text)?;
+                write_line(backend, "")?;
+                write_line(backend, "Press Y for yes, N for no, ESC to quit...")?;
+                backend.flush().ok();
                 
                 // Handle user input for query
-                use crossterm::event::{read, Event, KeyCode, KeyEvent};
                 loop {
-                    match read() {
+                    match backend.read_event() {
                         Ok(Event::Key(KeyEvent { code: KeyCode::Char('y'), .. })) |
                         Ok(Event::Key(KeyEvent { code: KeyCode::Char('Y'), .. })) => {
                             self.last_query_response = Some(true);
@@ -245,6 +388,13 @@ impl Executor {
                         Ok(Event::Key(KeyEvent { code: KeyCode::Esc, .. })) => {
                             return Ok(ExecutionResult::Exit);
                         },
+                        Ok(Event::Key(KeyEvent { code: KeyCode::F(n), .. })) => {
+                            if let Some(label) = self.key_bindings.get(&n) {
+                                self.jump_cause = Some(JumpCause::KeyBinding);
+                                return Ok(ExecutionResult::Jump(label.clone()));
+                            }
+                        },
+                        Err(e) => return Err(e.into()),
                         _ => continue,
                     }
                 }
@@ -266,18 +416,17 @@ impl Executor {
                 }
             },
             
+            Command::PerformanceGoto { condition, label } => {
+                // Nothing to test before the first drill or speed test
+                match self.last_result() {
+                    Some(result) if condition.holds(result) => Ok(ExecutionResult::Jump(label)),
+                    _ => Ok(ExecutionResult::Continue),
+                }
+            },
+            
             Command::TutorialFile { path } => {
-                match load_text_file(&path, &self.script.path) {
-                    Ok(text) => {
-                        let exercise = TutorialExercise::new(text);
-                        match exercise.execute() {
-                            Ok(ExerciseOutcome::Completed(_)) => Ok(ExecutionResult::Continue),
-                            Ok(ExerciseOutcome::Quit) => Ok(ExecutionResult::Exit),
-                            Ok(ExerciseOutcome::Retry) => Ok(ExecutionResult::Continue),
-                            Ok(ExerciseOutcome::Failed) => Ok(ExecutionResult::Continue),
-                            Err(_) => Ok(ExecutionResult::Continue),
-                        }
-                    },
+                match load_text_file(&path, self.script.source_file(self.script.position)) {
+                    Ok(text) => self.run_tutorial(text),
                     Err(e) => {
                         eprintln!("Error loading tutorial file '{}': {}", path, e);
                         Ok(ExecutionResult::Continue)
@@ -286,23 +435,8 @@ impl Executor {
             },
             
             Command::DrillFile { path, practice_only } => {
-                match load_text_file(&path, &self.script.path) {
-                    Ok(text) => {
-                        let exercise = DrillExercise::new(text, practice_only, self.error_percentage);
-                        match exercise.execute() {
-                            Ok(ExerciseOutcome::Completed(_)) => Ok(ExecutionResult::Continue),
-                            Ok(ExerciseOutcome::Quit) => Ok(ExecutionResult::Exit),
-                            Ok(ExerciseOutcome::Retry) => Ok(ExecutionResult::Continue),
-                            Ok(ExerciseOutcome::Failed) => {
-                                if let Some(ref label) = self.failure_label {
-                                    Ok(ExecutionResult::Jump(label.clone()))
-                                } else {
-                                    Ok(ExecutionResult::Continue)
-                                }
-                            },
-                            Err(_) => Ok(ExecutionResult::Continue),
-                        }
-                    },
+                match load_text_file(&path, self.script.source_file(self.script.position)) {
+                    Ok(text) => self.run_drill(text, practice_only),
                     Err(e) => {
                         eprintln!("Error loading drill file '{}': {}", path, e);
                         Ok(ExecutionResult::Continue)
@@ -311,17 +445,8 @@ impl Executor {
             },
             
             Command::SpeedTestFile { path, practice_only } => {
-                match load_text_file(&path, &self.script.path) {
-                    Ok(text) => {
-                        let exercise = SpeedTestExercise::new(text, practice_only, None);
-                        match exercise.execute() {
-                            Ok(ExerciseOutcome::Completed(_)) => Ok(ExecutionResult::Continue),
-                            Ok(ExerciseOutcome::Quit) => Ok(ExecutionResult::Exit),
-                            Ok(ExerciseOutcome::Retry) => Ok(ExecutionResult::Continue),
-                            Ok(ExerciseOutcome::Failed) => Ok(ExecutionResult::Continue),
-                            Err(_) => Ok(ExecutionResult::Continue),
-                        }
-                    },
+                match load_text_file(&path, self.script.source_file(self.script.position)) {
+                    Ok(text) => self.run_speed_test(text, practice_only),
                     Err(e) => {
                         eprintln!("Error loading speed test file '{}': {}", path, e);
                         Ok(ExecutionResult::Continue)
@@ -329,10 +454,11 @@ impl Executor {
                 }
             },
             
-            Command::KeyBind { sequence } => {
-                // TODO: Implement key binding functionality
-                // For now, just log and continue
-                println!("Key binding: {}", sequence);
+            Command::KeyBind { key, label } => {
+                match label {
+                    Some(label) => self.key_bindings.insert(key, label),
+                    None => self.key_bindings.remove(&key),
+                };
                 Ok(ExecutionResult::Continue)
             },
             
@@ -340,6 +466,190 @@ impl Executor {
     }
 }
 
+impl Executor {
+    /// Error pointing at the command being executed
+    fn error_here(&self, message: String) -> ScriptError {
+        match self.script.span(self.script.position) {
+            Some(span) => ScriptError::Located { span: span.clone(), message },
+            None => ScriptError::InvalidFormat { message },
+        }
+    }
+    
+    /// Show the menu at the current position and follow the user's choice
+    ///
+    /// Escape leaves through the menu's UP= target if it has one, and
+    /// otherwise goes back to the menu shown before it. Only Escape from
+    /// the first menu quits.
+    fn run_menu(&mut self) -> ScriptResult<ExecutionResult> {
+        loop {
+            let position = self.script.position;
+            let Some(Command::Menu { title, up, items }) = self.script.current_command().cloned() else {
+                return Ok(ExecutionResult::Continue);
+            };
+            
+            // Coming back to a menu already on the stack drops everything shown since
+            match self.menu_stack.iter().position(|&shown| shown == position) {
+                Some(index) => self.menu_stack.truncate(index + 1),
+                None => self.menu_stack.push(position),
+            }
+            
+            let mut menu = Menu::new(title);
+            for item in items {
+                menu.add_item(item.label, item.description);
+            }
+            
+            match menu.display(self.backend.as_mut()) {
+                Ok(Some(selected_label)) => {
+                    self.jump_cause = Some(JumpCause::Menu);
+                    return Ok(ExecutionResult::Jump(selected_label));
+                },
+                Ok(None) => {},
+                Err(e) => return Err(exercise_error(e)),
+            }
+            
+            self.menu_stack.pop();
+            self.jump_cause = Some(JumpCause::MenuUp);
+            match up {
+                Some(up) if up == MENU_EXIT_LABEL => return Ok(ExecutionResult::Exit),
+                Some(up) => return Ok(ExecutionResult::Jump(up)),
+                None => match self.menu_stack.last() {
+                    Some(&parent) => self.script.position = parent,
+                    None => return Ok(ExecutionResult::Exit),
+                },
+            }
+        }
+    }
+    
+    fn run_tutorial(&mut self, text: String) -> ScriptResult<ExecutionResult> {
+        let exercise = TutorialExercise::new(text)
+            .with_key_bindings(&self.key_bindings);
+        let outcome = exercise.execute(self.backend.as_mut());
+        self.finish_exercise(outcome, ExerciseKind::Tutorial)
+    }
+    
+    fn run_drill(&mut self, text: String, practice_only: bool) -> ScriptResult<ExecutionResult> {
+        let max_error = self.next_error_percentage.take().unwrap_or(self.error_percentage);
+        let options = self.next_typing_options.take().unwrap_or(self.typing_options);
+        let exercise = DrillExercise::new(text, practice_only, max_error)
+            .with_key_bindings(&self.key_bindings)
+            .with_options(options);
+        let outcome = exercise.execute(self.backend.as_mut());
+        self.finish_exercise(outcome, ExerciseKind::Drill)
+    }
+    
+    fn run_speed_test(&mut self, text: String, practice_only: bool) -> ScriptResult<ExecutionResult> {
+        // Speed tests have no error limit, but still use up a one-shot E:
+        self.next_error_percentage = None;
+        let options = self.next_typing_options.take().unwrap_or(self.typing_options);
+        let exercise = SpeedTestExercise::new(text, practice_only, None) // No time limit by default
+            .with_key_bindings(&self.key_bindings)
+            .with_options(options);
+        let outcome = exercise.execute(self.backend.as_mut());
+        self.finish_exercise(outcome, ExerciseKind::SpeedTest)
+    }
+    
+    /// Turn an exercise outcome into the next execution step
+    fn finish_exercise(
+        &mut self,
+        outcome: Result<ExerciseOutcome, Box<dyn std::error::Error>>,
+        kind: ExerciseKind,
+    ) -> ScriptResult<ExecutionResult> {
+        // A one-shot F: only covers the exercise that just ran
+        let failure_label = match kind {
+            ExerciseKind::Tutorial => None,
+            _ => self.next_failure_label.take().unwrap_or_else(|| self.failure_label.clone()),
+        };
+        if let Ok(outcome) = &outcome {
+            let index = self.script.position;
+            self.trace(|_| TraceEvent::Outcome { index, exercise: kind, outcome: outcome.clone() })?;
+        }
+        match outcome {
+            Ok(ExerciseOutcome::Completed(result)) => {
+                // Tutorials have nothing worth recording
+                if kind != ExerciseKind::Tutorial {
+                    self.results.push(result);
+                }
+                Ok(ExecutionResult::Continue)
+            },
+            Ok(ExerciseOutcome::Quit) => Ok(ExecutionResult::Exit),
+            Ok(ExerciseOutcome::Retry) => {
+                self.jump_cause = Some(JumpCause::Repeat);
+                Ok(ExecutionResult::Repeat)
+            },
+            Ok(ExerciseOutcome::Failed(result)) => {
+                // Failed attempts count too, so J: judges the drill just typed
+                if kind != ExerciseKind::Tutorial {
+                    self.results.push(result);
+                }
+                // Jump to failure label if set, otherwise let the learner
+                // choose. Tutorials and speed tests don't typically fail.
+                match (&kind, failure_label) {
+                    (ExerciseKind::Drill, Some(label)) => {
+                        self.jump_cause = Some(JumpCause::Failure);
+                        Ok(ExecutionResult::Jump(label))
+                    },
+                    (ExerciseKind::Drill, None) => self.ask_after_failed_drill(),
+                    _ => Ok(ExecutionResult::Continue),
+                }
+            },
+            Ok(ExerciseOutcome::Jump(label)) => {
+                self.jump_cause = Some(JumpCause::KeyBinding);
+                Ok(ExecutionResult::Jump(label))
+            },
+            Err(e) => Err(exercise_error(e)),
+        }
+    }
+}
+
+impl Executor {
+    /// Ask what to do after a drill went over its error limit with no F:
+    /// label to go to: repeat it (the default, as in C gtypist), go on to
+    /// the next command, or go back to the menu the drill was chosen from
+    fn ask_after_failed_drill(&mut self) -> ScriptResult<ExecutionResult> {
+        let menu = self.menu_stack.last().copied();
+        let backend = self.backend.as_mut();
+        backend.clear_screen()?;
+        
+        write_line(backend, "")?;
+        write_centered(backend, "=== TOO MANY ERRORS ===")?;
+        write_line(backend, "")?;
+        write_line(backend, "  R  Repeat the drill")?;
+        write_line(backend, "  N  Go on to the next exercise")?;
+        if menu.is_some() {
+            write_line(backend, "  M  Back to the menu")?;
+        }
+        write_line(backend, "")?;
+        write_line(backend, "Press R (or ENTER) to repeat, ESC to quit...")?;
+        backend.flush().ok();
+        
+        loop {
+            let Event::Key(KeyEvent { code, .. }) = backend.read_event()? else { continue };
+            match code {
+                KeyCode::Char('r') | KeyCode::Char('R') | KeyCode::Enter => {
+                    self.jump_cause = Some(JumpCause::Repeat);
+                    return Ok(ExecutionResult::Repeat);
+                },
+                KeyCode::Char('n') | KeyCode::Char('N') => return Ok(ExecutionResult::Continue),
+                KeyCode::Char('m') | KeyCode::Char('M') => {
+                    if let Some(menu) = menu {
+                        self.script.position = menu;
+                        self.jump_cause = Some(JumpCause::Menu);
+                        return Ok(ExecutionResult::Repeat);
+                    }
+                },
+                KeyCode::Esc => return Ok(ExecutionResult::Exit),
+                KeyCode::F(n) => {
+                    if let Some(label) = self.key_bindings.get(&n) {
+                        self.jump_cause = Some(JumpCause::KeyBinding);
+                        return Ok(ExecutionResult::Jump(label.clone()));
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+}
+
 /// Result of executing a command
 #[derive(Debug, Clone)]
 pub enum ExecutionResult {
@@ -347,6 +657,9 @@ pub enum ExecutionResult {
     Continue,
     /// Jump to a specific label
     Jump(String),
+    /// Run the command at the current position rather than moving past
+    /// it; `execute_next` reports this as `Continue`
+    Repeat,
     /// Exit the script
     Exit,
     /// Script execution finished
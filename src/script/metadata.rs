@@ -0,0 +1,123 @@
+//! Lesson metadata header
+//!
+//! A lesson can describe itself with directives in the comments at the
+//! top of the file, before its first command:
+//!
+//! ```text
+//! #@title: Quick QWERTY course
+//! #@layout: de
+//! #@language: German
+//! #@author: Jane Doe
+//! #@version: 1.2
+//! #@entry: Q_MENU
+//! ```
+//!
+//! To anything else, including the C implementation, these are ordinary
+//! comments. Unknown keys are ignored, as are directives further down
+//! the file. `#@charset:` is also a header directive, but is read before
+//! parsing; see [`crate::script::encoding`].
+
+use serde::{Deserialize, Serialize};
+use crate::script::commands::Command;
+
+/// Marker that turns a comment into a metadata directive
+pub const DIRECTIVE_PREFIX: &str = "#@";
+
+/// What a lesson says about itself in its header
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LessonMetadata {
+    pub title: Option<String>,
+    /// Keyboard layout the lesson is written for, e.g. `us` or `de`
+    pub layout: Option<String>,
+    pub language: Option<String>,
+    pub author: Option<String>,
+    pub version: Option<String>,
+    /// Label the lesson should normally be started from
+    pub entry: Option<String>,
+}
+
+impl LessonMetadata {
+    /// Read the directives in the comments before the first command
+    ///
+    /// When a key is given more than once, the first value wins.
+    pub fn from_commands(commands: &[Command]) -> Self {
+        let mut metadata = Self::default();
+        for (key, value) in header_directives(commands).map(|(_, directive)| directive) {
+            let field = match key.to_ascii_lowercase().as_str() {
+                "title" => &mut metadata.title,
+                "layout" => &mut metadata.layout,
+                "language" => &mut metadata.language,
+                "author" => &mut metadata.author,
+                "version" => &mut metadata.version,
+                "entry" => &mut metadata.entry,
+                _ => continue,
+            };
+            field.get_or_insert_with(|| value.to_string());
+        }
+        metadata
+    }
+
+    /// True if the lesson declares nothing about itself
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Header directives as `(command index, (key, value))`
+pub(crate) fn header_directives(commands: &[Command]) -> impl Iterator<Item = (usize, (&str, &str))> {
+    commands.iter().enumerate()
+        .map_while(|(index, command)| match command {
+            Command::Comment { content } => Some((index, content)),
+            _ => None,
+        })
+        .filter_map(|(index, content)| directive(content).map(|directive| (index, directive)))
+}
+
+/// Split a `#@key: value` comment into its key and value
+fn directive(comment: &str) -> Option<(&str, &str)> {
+    let (key, value) = comment.strip_prefix(DIRECTIVE_PREFIX)?.split_once(':')?;
+    let (key, value) = (key.trim(), value.trim());
+    if key.is_empty() || value.is_empty() {
+        return None;
+    }
+    Some((key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comment(content: &str) -> Command {
+        Command::Comment { content: content.to_string() }
+    }
+
+    #[test]
+    fn test_reads_header_directives() {
+        let commands = vec![
+            comment("# GNU Typist lesson"),
+            comment("#@title: German basics"),
+            comment("#@ Layout : de"),
+            comment("#@author:"),
+            comment("#@entry: MENU"),
+            comment("#@title: Ignored repeat"),
+            comment("#@colour: blue"),
+            Command::Label { name: "MENU".to_string() },
+            comment("#@version: 2.0"),
+        ];
+
+        let metadata = LessonMetadata::from_commands(&commands);
+
+        assert_eq!(metadata, LessonMetadata {
+            title: Some("German basics".to_string()),
+            layout: Some("de".to_string()),
+            entry: Some("MENU".to_string()),
+            ..LessonMetadata::default()
+        });
+    }
+
+    #[test]
+    fn test_plain_comments_are_not_metadata() {
+        let commands = vec![comment("# title: not a directive"), comment("#@no separator")];
+        assert!(LessonMetadata::from_commands(&commands).is_empty());
+    }
+}
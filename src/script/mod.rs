@@ -6,11 +6,20 @@
 pub mod commands;
 pub mod parser;
 pub mod executor;
+pub mod writer;
+pub mod structured;
+pub mod metadata;
+pub mod search;
+pub mod encoding;
+pub mod state;
+pub mod trace;
 
 use std::collections::HashMap;
+use std::fmt;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::fs;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 /// Script parsing and execution errors
@@ -22,8 +31,8 @@ pub enum ScriptError {
     #[error("Invalid command format in line {line}: {content}")]
     InvalidCommand { line: usize, content: String },
     
-    #[error("Label not found: {label}")]
-    LabelNotFound { label: String },
+    #[error("Label not found: {label}{}", .span.as_ref().map(|s| format!(" (referenced at {})", s)).unwrap_or_default())]
+    LabelNotFound { label: String, span: Option<SourceSpan> },
     
     #[error("Invalid script format: {message}")]
     InvalidFormat { message: String },
@@ -33,11 +42,56 @@ pub enum ScriptError {
     
     #[error("File error: {0}")]
     FileError(String),
+    
+    /// A lesson file whose encoding is unknown or doesn't match its bytes
+    #[error("{path}: {message}")]
+    Encoding { path: String, message: String },
+    
+    #[error("Lesson not found: {name}; tried:{}", .tried.iter().map(|p| format!("\n  {}", p.display())).collect::<String>())]
+    LessonNotFound { name: String, tried: Vec<PathBuf> },
+    
+    /// A problem tied to a source position, such as a bad include
+    #[error("{span}: {message}")]
+    Located { span: SourceSpan, message: String },
 }
 
 /// Result type for script operations
 pub type ScriptResult<T> = Result<T, ScriptError>;
 
+/// Where a command came from in its source file
+///
+/// Lines and columns are 1-based; `end_column` is exclusive and refers to
+/// the first line. Commands with continuation lines end on `end_line`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceSpan {
+    pub file: String,
+    pub line: usize,
+    pub end_line: usize,
+    pub column: usize,
+    pub end_column: usize,
+}
+
+impl SourceSpan {
+    /// Span covering the non-blank text of a single source line
+    pub fn of_line(file: &str, line_number: usize, line: &str) -> Self {
+        let trimmed = line.trim_start();
+        let column = line.chars().count() - trimmed.chars().count() + 1;
+        Self {
+            file: file.to_string(),
+            line: line_number,
+            end_line: line_number,
+            column,
+            end_column: column + trimmed.trim_end().chars().count(),
+        }
+    }
+}
+
+impl fmt::Display for SourceSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
 /// A parsed lesson script with indexed labels
 #[derive(Debug, Clone)]
 pub struct Script {
@@ -47,14 +101,41 @@ pub struct Script {
     pub commands: Vec<commands::Command>,
     /// Label index for fast navigation (label -> command index)
     pub labels: HashMap<String, usize>,
+    /// Source position of each command, parallel to `commands`
+    pub spans: Vec<SourceSpan>,
+    /// Title, layout and other details declared in the lesson header
+    pub metadata: metadata::LessonMetadata,
     /// Current execution position
     pub position: usize,
 }
 
 impl Script {
     /// Create a new script from a file path
+    ///
+    /// `.json` and `.toml` files are read as structured lessons, anything
+    /// else as lesson script syntax.
     pub fn from_file(path: &str) -> ScriptResult<Self> {
-        parser::parse_script_file(path)
+        match structured::LessonFormat::from_path(path) {
+            structured::LessonFormat::Typ => parser::parse_script_file(path),
+            format => structured::parse_structured_file(path, format),
+        }
+    }
+    
+    /// Parse a script file, collecting every invalid line instead of
+    /// stopping at the first one
+    ///
+    /// Invalid lines are left out of the returned script. Only failing to
+    /// read the file is returned as an error.
+    pub fn from_file_recovering(path: &str) -> ScriptResult<(Self, Vec<ScriptError>)> {
+        match structured::LessonFormat::from_path(path) {
+            structured::LessonFormat::Typ => parser::parse_script_file_recovering(path),
+            format => structured::parse_structured_file(path, format).map(|script| (script, Vec::new())),
+        }
+    }
+    
+    /// Write the script back out as lesson script text
+    pub fn to_typ(&self) -> ScriptResult<String> {
+        writer::write_script(self)
     }
     
     /// Jump to a specific label
@@ -64,17 +145,42 @@ impl Script {
             Ok(())
         } else {
             Err(ScriptError::LabelNotFound { 
-                label: label.to_string() 
+                label: label.to_string(),
+                span: None,
             })
         }
     }
     
+    /// Jump to a label named by the command at `from`, reporting that
+    /// command's position if the label does not exist
+    pub fn goto_label_from(&mut self, label: &str, from: usize) -> ScriptResult<()> {
+        self.goto_label(label).map_err(|e| match e {
+            ScriptError::LabelNotFound { label, .. } => ScriptError::LabelNotFound {
+                label,
+                span: self.spans.get(from).cloned(),
+            },
+            other => other,
+        })
+    }
+    
+    /// Source position of the command at `index`
+    pub fn span(&self, index: usize) -> Option<&SourceSpan> {
+        self.spans.get(index)
+    }
+    
+    /// File the command at `index` came from, which differs from `path`
+    /// for commands spliced in by an include
+    pub fn source_file(&self, index: usize) -> &str {
+        self.spans.get(index).map_or(&self.path, |span| &span.file)
+    }
+    
     /// Get the current command
     pub fn current_command(&self) -> Option<&commands::Command> {
         self.commands.get(self.position)
     }
     
     /// Advance to next command
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Option<&commands::Command> {
         self.position += 1;
         self.current_command()
@@ -86,8 +192,11 @@ impl Script {
     }
 }
 
-/// Load text content from a file, resolving the path relative to the script directory
-pub fn load_text_file(file_path: &str, script_path: &str) -> ScriptResult<String> {
+/// Resolve a file referenced by a script relative to the script directory
+///
+/// Fails if the file does not exist or resolves to a location outside the
+/// directory containing the script.
+pub fn resolve_script_path(file_path: &str, script_path: &str) -> ScriptResult<PathBuf> {
     // Get the directory containing the script file
     let script_dir = Path::new(script_path)
         .parent()
@@ -110,6 +219,13 @@ pub fn load_text_file(file_path: &str, script_path: &str) -> ScriptResult<String
         )));
     }
     
+    Ok(full_path)
+}
+
+/// Load text content from a file, resolving the path relative to the script directory
+pub fn load_text_file(file_path: &str, script_path: &str) -> ScriptResult<String> {
+    let full_path = resolve_script_path(file_path, script_path)?;
+    
     // Check file size to prevent memory issues (limit to 1MB)
     const MAX_FILE_SIZE: u64 = 1024 * 1024; // 1MB
     let metadata = fs::metadata(&full_path)
@@ -122,9 +238,10 @@ pub fn load_text_file(file_path: &str, script_path: &str) -> ScriptResult<String
         )));
     }
     
-    // Read and return the file content
-    let content = fs::read_to_string(&full_path)
+    // Read the file and decode it like a lesson, legacy code pages included
+    let bytes = fs::read(&full_path)
         .map_err(|e| ScriptError::FileError(format!("Cannot read file '{}': {}", file_path, e)))?;
+    let (content, _) = encoding::decode_lesson(&bytes, file_path)?;
     
     Ok(content)
-}
\ No newline at end of file
+}
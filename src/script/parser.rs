@@ -2,46 +2,136 @@
 //! 
 //! Handles parsing lesson script files and building label indices,
 //! replicating the functionality from the C implementation's build_label_index()
+//!
+//! An include line (`@:common.typ`) splices another script in place. Its
+//! labels must not clash with labels from other files unless it is given
+//! a namespace (`@:common.typ:COMMON`), which renames each label `L`
+//! defined in the included file to `COMMON.L`, along with every jump to
+//! it from inside that file.
 
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use crate::script::{Script, ScriptResult};
-use crate::script::commands::{Command, MenuItem};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::script::{Script, ScriptError, ScriptResult, SourceSpan, encoding, resolve_script_path};
+use crate::script::commands::{chars, Command, MenuItem};
+use crate::script::metadata::LessonMetadata;
+use crate::script::structured::{self, LessonFormat};
 
 /// Parse a script file and build the complete Script structure
+///
+/// Stops at the first invalid line.
 pub fn parse_script_file(path: &str) -> ScriptResult<Script> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-    
+    parse(path, false).map(|(script, _)| script)
+}
+
+/// Parse a script file, collecting all invalid lines instead of stopping
+///
+/// Invalid lines and their continuation lines are skipped, so the
+/// returned script holds everything that did parse.
+pub fn parse_script_file_recovering(path: &str) -> ScriptResult<(Script, Vec<ScriptError>)> {
+    parse(path, true)
+}
+
+fn parse(path: &str, recover: bool) -> ScriptResult<(Script, Vec<ScriptError>)> {
+    parse_file(path, recover, &mut Vec::new())
+}
+
+/// Parse one file; `include_stack` holds the canonical paths of the files
+/// currently being included, outermost first
+///
+/// The file may be in any encoding [`encoding::decode_lesson`] can detect.
+fn parse_file(path: &str, recover: bool, include_stack: &mut Vec<PathBuf>) -> ScriptResult<(Script, Vec<ScriptError>)> {
+    let (content, _) = encoding::decode_lesson(&fs::read(path)?, path)?;
+    include_stack.push(Path::new(path).canonicalize()?);
+    let result = parse_lines(path, &content, recover, include_stack);
+    include_stack.pop();
+    result
+}
+
+fn parse_lines(
+    path: &str,
+    content: &str,
+    recover: bool,
+    include_stack: &mut Vec<PathBuf>,
+) -> ScriptResult<(Script, Vec<ScriptError>)> {    
     let mut commands = Vec::new();
     let mut labels = HashMap::new();
+    let mut spans: Vec<SourceSpan> = Vec::new();
+    let mut errors = Vec::new();
     let mut line_number = 0;
     let mut pending_menu: Option<(usize, String)> = None; // (command_index, title)
+    // Set after an invalid line so its continuation lines are dropped too
+    let mut skipping = false;
     
-    for line in reader.lines() {
+    for line in content.lines() {
         line_number += 1;
-        let line = line?;
         
         // Handle continuation lines (starting with space)
         if line.starts_with(' ') {
+            if skipping {
+                continue;
+            }
             if let Some((menu_idx, _)) = &pending_menu {
                 // This is a menu item line
-                parse_menu_item(&line, &mut commands, *menu_idx)?;
+                parse_menu_item(line, &mut commands, *menu_idx)?;
             } else {
                 // This is a tutorial/instruction continuation line, append to last command
-                append_continuation_line(&line, &mut commands)?;
+                append_continuation_line(line, &mut commands)?;
+            }
+            if let Some(span) = spans.last_mut() {
+                span.end_line = line_number;
             }
             continue;
         } else {
             pending_menu = None;
+            skipping = false;
         }
         
-        if let Some(command) = Command::parse_line(&line, line_number)? {
+        if let Some(directive) = include_directive(line) {
+            // Continuation lines have nothing to attach to after an include
+            skipping = true;
+            let span = SourceSpan::of_line(path, line_number, line);
+            let result = include(path, directive, &span, recover, include_stack)
+                .and_then(|(included, included_errors)| {
+                    errors.extend(included_errors);
+                    splice(&mut commands, &mut labels, &mut spans, included, &span)
+                });
+            match result {
+                Ok(()) => {},
+                Err(e) if recover => errors.push(e),
+                Err(e) => return Err(e),
+            }
+            continue;
+        }
+        
+        let command = match Command::parse_line(line, line_number) {
+            Ok(command) => command,
+            Err(e) if recover => {
+                errors.push(e);
+                skipping = true;
+                continue;
+            },
+            Err(e) => return Err(e),
+        };
+        
+        if let Some(command) = command {
             let command_index = commands.len();
             
             // Index labels for fast navigation
             if let Command::Label { ref name } = command {
+                if let Some(&existing) = labels.get(name) {
+                    if spans[existing].file != path {
+                        let error = ScriptError::Located {
+                            span: SourceSpan::of_line(path, line_number, line),
+                            message: format!("label '{}' is already defined at {}", name, spans[existing]),
+                        };
+                        if recover {
+                            errors.push(error);
+                            continue;
+                        }
+                        return Err(error);
+                    }
+                }
                 labels.insert(name.clone(), command_index);
             }
             
@@ -51,19 +141,120 @@ pub fn parse_script_file(path: &str) -> ScriptResult<Script> {
             }
             
             commands.push(command);
+            spans.push(SourceSpan::of_line(path, line_number, line));
         }
     }
     
-    Ok(Script {
+    let script = Script {
         path: path.to_string(),
+        metadata: LessonMetadata::from_commands(&commands),
         commands,
         labels,
+        spans,
         position: 0,
+    };
+    Ok((script, errors))
+}
+
+/// Split an include line into its path and optional namespace
+fn include_directive(line: &str) -> Option<(&str, Option<&str>)> {
+    let data = line.strip_prefix(chars::INCLUDE)?.strip_prefix(chars::SEP)?.trim();
+    Some(match data.split_once(chars::SEP) {
+        Some((file, namespace)) => (file.trim(), Some(namespace.trim())),
+        None => (data, None),
     })
 }
 
+/// Parse an included file, with its labels namespaced if requested
+fn include(
+    includer: &str,
+    (file, namespace): (&str, Option<&str>),
+    span: &SourceSpan,
+    recover: bool,
+    include_stack: &mut Vec<PathBuf>,
+) -> ScriptResult<(Script, Vec<ScriptError>)> {
+    let located = |message: String| ScriptError::Located { span: span.clone(), message };
+    
+    if file.is_empty() || namespace == Some("") {
+        return Err(located("include needs a file and an optional non-empty namespace".to_string()));
+    }
+    
+    let resolved = resolve_script_path(file, includer).map_err(|e| located(e.to_string()))?;
+    let canonical = resolved.canonicalize()?;
+    if let Some(start) = include_stack.iter().position(|p| *p == canonical) {
+        let cycle: Vec<String> = include_stack[start..].iter()
+            .chain(std::iter::once(&canonical))
+            .map(|p| p.display().to_string())
+            .collect();
+        return Err(located(format!("include cycle: {}", cycle.join(" -> "))));
+    }
+    
+    let resolved = resolved.to_string_lossy().into_owned();
+    let parsed = match LessonFormat::from_path(&resolved) {
+        LessonFormat::Typ => parse_file(&resolved, recover, include_stack),
+        format => structured::parse_structured_file(&resolved, format).map(|script| (script, Vec::new())),
+    };
+    let (mut script, errors) = parsed.map_err(|e| located_in(&resolved, e, span))?;
+    let errors = errors.into_iter().map(|e| located_in(&resolved, e, span)).collect();
+    
+    if let Some(namespace) = namespace {
+        let own: HashSet<String> = script.labels.keys().cloned().collect();
+        for command in &mut script.commands {
+            for label in command.labels_mut() {
+                if own.contains(label.as_str()) {
+                    *label = format!("{}.{}", namespace, label);
+                }
+            }
+        }
+        script.labels = script.labels.into_iter()
+            .map(|(name, index)| (format!("{}.{}", namespace, name), index))
+            .collect();
+    }
+    
+    Ok((script, errors))
+}
+
+/// Attach the file name to an error from an included file
+fn located_in(file: &str, error: ScriptError, include_span: &SourceSpan) -> ScriptError {
+    match error {
+        ScriptError::Located { .. } => error,
+        ScriptError::InvalidCommand { line, .. } => ScriptError::Located {
+            span: SourceSpan { file: file.to_string(), line, end_line: line, column: 1, end_column: 1 },
+            message: error.to_string(),
+        },
+        other => ScriptError::Located {
+            span: include_span.clone(),
+            message: format!("{}: {}", file, other),
+        },
+    }
+}
+
+/// Append an included script, refusing labels that already exist
+fn splice(
+    commands: &mut Vec<Command>,
+    labels: &mut HashMap<String, usize>,
+    spans: &mut Vec<SourceSpan>,
+    included: Script,
+    span: &SourceSpan,
+) -> ScriptResult<()> {
+    for name in included.labels.keys() {
+        if let Some(&existing) = labels.get(name) {
+            return Err(ScriptError::Located {
+                span: span.clone(),
+                message: format!("included label '{}' is already defined at {}", name, spans[existing]),
+            });
+        }
+    }
+    
+    let offset = commands.len();
+    labels.extend(included.labels.into_iter().map(|(name, index)| (name, index + offset)));
+    commands.extend(included.commands);
+    spans.extend(included.spans);
+    Ok(())
+}
+
 /// Parse a menu item line (format: " :LABEL  \"Description\"")
-fn parse_menu_item(line: &str, commands: &mut Vec<Command>, menu_index: usize) -> ScriptResult<()> {
+fn parse_menu_item(line: &str, commands: &mut [Command], menu_index: usize) -> ScriptResult<()> {
     let line = line.trim();
     
     // Skip empty continuation lines or lines with just ":"
@@ -101,24 +292,24 @@ fn parse_menu_item(line: &str, commands: &mut Vec<Command>, menu_index: usize) -
 }
 
 /// Append a continuation line to the last command's text
-fn append_continuation_line(line: &str, commands: &mut Vec<Command>) -> ScriptResult<()> {
-    let line = line.trim();
+fn append_continuation_line(line: &str, commands: &mut [Command]) -> ScriptResult<()> {
+    let untrimmed = line.trim_start();
     
-    // Skip empty continuation lines
-    if line.is_empty() || line == ":" {
+    // Skip lines holding nothing but spaces
+    if untrimmed.is_empty() {
         return Ok(());
     }
     
-    // Remove the leading ":" if present
-    let text_to_append = if line.starts_with(':') {
-        &line[1..]
-    } else {
-        line
-    };
+    // Text after the leading ":" if present, exactly as written
+    let exact_text = untrimmed.strip_prefix(':').unwrap_or(untrimmed);
+    let line = untrimmed.trim_end();
+    let text_to_append = exact_text.trim();
     
     // Find the last command that can accept continuation text
     if let Some(last_cmd) = commands.last_mut() {
         match last_cmd {
+            // Tutorial and instruction lines are joined, so blank lines add nothing
+            Command::Tutorial { .. } | Command::Instruction { .. } if text_to_append.is_empty() => {},
             Command::Tutorial { ref mut text } => {
                 if !text.is_empty() {
                     text.push(' ');
@@ -131,17 +322,15 @@ fn append_continuation_line(line: &str, commands: &mut Vec<Command>) -> ScriptRe
                 }
                 text.push_str(text_to_append);
             },
-            Command::Drill { ref mut text, .. } => {
-                if !text.is_empty() {
-                    text.push(' ');
-                }
-                text.push_str(text_to_append);
-            },
+            // Drills and speed tests keep their line structure, blank lines
+            // and trailing spaces included, so the typist has to press
+            // Enter between lines
+            Command::Drill { ref mut text, .. } |
             Command::SpeedTest { ref mut text, .. } => {
                 if !text.is_empty() {
-                    text.push(' ');
+                    text.push('\n');
                 }
-                text.push_str(text_to_append);
+                text.push_str(exact_text);
             },
             Command::Menu { ref mut items, .. } => {
                 // Parse menu item line: " :LABEL  "description""
@@ -213,14 +402,28 @@ T:This is lesson 1
             matches!(cmd, Command::Menu { .. })
         }).unwrap();
         
-        if let Command::Menu { title, items } = menu_cmd {
+        if let Command::Menu { title, up, items } = menu_cmd {
             assert_eq!(title, "Main Menu");
+            assert_eq!(up, &None);
             assert_eq!(items.len(), 3);
             assert_eq!(items[0].label, "LESSON1");
             assert_eq!(items[0].description, "Basic typing lesson");
         }
     }
     
+    #[test]
+    fn test_parse_menu_up_target() {
+        assert_eq!(
+            Command::parse_line("M: UP=__SERIESMENU \"The Q series\"", 1).unwrap(),
+            Some(Command::Menu { title: "The Q series".to_string(), up: Some("__SERIESMENU".to_string()), items: Vec::new() })
+        );
+        assert!(matches!(
+            Command::parse_line("M:UP=_EXIT Lessons", 1).unwrap(),
+            Some(Command::Menu { title, up: Some(up), .. }) if title == "Lessons" && up == "_EXIT"
+        ));
+        assert!(Command::parse_line("M: UP= \"Title\"", 1).is_err());
+    }
+    
     #[test]
     fn test_command_parsing() {
         assert!(matches!(
@@ -243,4 +446,235 @@ T:This is lesson 1
             Some(Command::Label { name }) if name == "LABEL_NAME"
         ));
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_drill_continuation_keeps_lines() {
+        let script_content = "D:asdf ;lkj\n :fdsa jkl;\nS:one\n :two\n";
+        
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(script_content.as_bytes()).unwrap();
+        
+        let script = parse_script_file(temp_file.path().to_str().unwrap()).unwrap();
+        
+        assert_eq!(script.commands[0], Command::Drill {
+            text: "asdf ;lkj\nfdsa jkl;".to_string(),
+            practice_only: false,
+        });
+        assert_eq!(script.commands[1], Command::SpeedTest {
+            text: "one\ntwo".to_string(),
+            practice_only: false,
+        });
+    }
+    
+    #[test]
+    fn test_drill_continuation_keeps_blank_lines_and_spaces() {
+        let script_content = "D:ab\n : \n :\n :cd  \nT:one\n :\n :two \n";
+        
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(script_content.as_bytes()).unwrap();
+        
+        let script = parse_script_file(temp_file.path().to_str().unwrap()).unwrap();
+        
+        assert_eq!(script.commands[0], Command::Drill {
+            text: "ab\n \n\ncd  ".to_string(),
+            practice_only: false,
+        });
+        assert_eq!(script.commands[1], Command::Tutorial { text: "one two".to_string() });
+    }
+    
+    #[test]
+    fn test_key_binding_parsing() {
+        assert_eq!(
+            Command::parse_line("K:12:MENU", 1).unwrap(),
+            Some(Command::KeyBind { key: 12, label: Some("MENU".to_string()) })
+        );
+        assert_eq!(
+            Command::parse_line("K:12:NULL", 1).unwrap(),
+            Some(Command::KeyBind { key: 12, label: None })
+        );
+        assert!(Command::parse_line("K:13:MENU", 1).is_err());
+        assert!(Command::parse_line("K:MENU", 1).is_err());
+        assert!(Command::parse_line("K:1:", 1).is_err());
+    }
+    
+    #[test]
+    fn test_command_spans() {
+        let script_content = "*:START\nT:first\n :second\n\tG:START\n";
+        
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(script_content.as_bytes()).unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        
+        let script = parse_script_file(path).unwrap();
+        
+        assert_eq!(script.spans.len(), script.commands.len());
+        assert_eq!(script.spans[1], SourceSpan {
+            file: path.to_string(),
+            line: 2,
+            end_line: 3,
+            column: 1,
+            end_column: 8,
+        });
+        assert_eq!((script.spans[2].line, script.spans[2].column), (4, 2));
+        assert_eq!(script.spans[2].to_string(), format!("{}:4:2", path));
+    }
+    
+    #[test]
+    fn test_legacy_encoded_script() {
+        // "D:Привет мир" in KOI8-R, which isn't valid UTF-8
+        let script_content = b"*:START\nD:\xf0\xd2\xc9\xd7\xc5\xd4 \xcd\xc9\xd2\n";
+        
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(script_content).unwrap();
+        
+        let script = parse_script_file(temp_file.path().to_str().unwrap()).unwrap();
+        assert_eq!(script.commands[1], Command::Drill { text: "Привет мир".to_string(), practice_only: false });
+        
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(b"D:\x81\x8d\x8f\x90\x9d\n").unwrap();
+        let error = parse_script_file(temp_file.path().to_str().unwrap()).unwrap_err();
+        assert!(matches!(error, ScriptError::Encoding { .. }), "{}", error);
+    }
+    
+    #[test]
+    fn test_recovering_parse_collects_all_errors() {
+        let script_content = "*:START\nnot a command\n :continued\nK:13:START\nT:still parsed\n";
+        
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(script_content.as_bytes()).unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        
+        assert!(parse_script_file(path).is_err());
+        
+        let (script, errors) = parse_script_file_recovering(path).unwrap();
+        let error_lines: Vec<usize> = errors.iter().map(|e| match e {
+            ScriptError::InvalidCommand { line, .. } => *line,
+            other => panic!("unexpected error {}", other),
+        }).collect();
+        assert_eq!(error_lines, vec![2, 4]);
+        assert_eq!(script.commands, vec![
+            Command::Label { name: "START".to_string() },
+            Command::Tutorial { text: "still parsed".to_string() },
+        ]);
+        assert_eq!(script.spans[1].line, 5);
+    }
+    
+    #[test]
+    fn test_performance_goto_parsing() {
+        use crate::script::commands::{Comparison, Condition, Metric};
+        
+        assert_eq!(
+            Command::parse_line("J:wpm < 30:REMEDIAL", 1).unwrap(),
+            Some(Command::PerformanceGoto {
+                condition: Condition { metric: Metric::Wpm, comparison: Comparison::Less, value: 30.0 },
+                label: "REMEDIAL".to_string(),
+            })
+        );
+        assert_eq!(
+            Condition::parse("Accuracy>=97.5%"),
+            Some(Condition { metric: Metric::Accuracy, comparison: Comparison::GreaterOrEqual, value: 97.5 })
+        );
+        assert!(Command::parse_line("J:speed < 30:LABEL", 1).is_err());
+        assert!(Command::parse_line("J:wpm << 30:LABEL", 1).is_err());
+        assert!(Command::parse_line("J:wpm < 30", 1).is_err());
+        assert!(Command::parse_line("J:wpm < 30:", 1).is_err());
+    }
+    
+    fn write_files(dir: &tempfile::TempDir, files: &[(&str, &str)]) -> String {
+        for (name, content) in files {
+            let path = dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        dir.path().join(files[0].0).to_str().unwrap().to_string()
+    }
+    
+    #[test]
+    fn test_include_splices_commands() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let main = write_files(&dir, &[
+            ("main.typ", "*:START\n@:shared/drills.typ\nG:DRILLS\n*:END\nX:\n"),
+            ("shared/drills.typ", "*:DRILLS\nD:asdf\nG:END\n"),
+        ]);
+        
+        let script = parse_script_file(&main).unwrap();
+        
+        assert_eq!(script.commands.len(), 7);
+        assert_eq!(script.labels["DRILLS"], 1);
+        assert_eq!(script.labels["END"], 5);
+        assert!(script.spans[2].file.ends_with("drills.typ"));
+        assert_eq!(script.spans[2].line, 2);
+        assert_eq!(script.spans[4].file, main);
+        assert_eq!(script.spans[4].line, 3);
+    }
+    
+    #[test]
+    fn test_include_with_namespace() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let main = write_files(&dir, &[
+            ("main.typ", "*:LOOP\n@:a.typ:A\n@:a.typ:B\nG:B.LOOP\n"),
+            ("a.typ", "*:LOOP\nD:asdf\nG:LOOP\nN:MENU\n"),
+        ]);
+        
+        let script = parse_script_file(&main).unwrap();
+        
+        assert_eq!(script.labels["LOOP"], 0);
+        assert_eq!(script.labels["A.LOOP"], 1);
+        assert_eq!(script.labels["B.LOOP"], 5);
+        assert_eq!(script.commands[3], Command::Goto { label: "A.LOOP".to_string() });
+        assert_eq!(script.commands[4], Command::NoGoto { label: "MENU".to_string() });
+    }
+    
+    #[test]
+    fn test_include_label_collision() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let main = write_files(&dir, &[
+            ("main.typ", "*:START\n@:a.typ\n"),
+            ("a.typ", "*:START\n"),
+        ]);
+        let error = parse_script_file(&main).unwrap_err();
+        assert!(error.to_string().contains("included label 'START' is already defined"), "{}", error);
+        
+        let main = write_files(&dir, &[
+            ("later.typ", "@:a.typ\n*:START\n"),
+        ]);
+        let error = parse_script_file(&main).unwrap_err();
+        assert!(matches!(error, ScriptError::Located { ref span, .. } if span.line == 2), "{}", error);
+    }
+    
+    #[test]
+    fn test_include_cycle_and_sandbox() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let main = write_files(&dir, &[
+            ("sub/a.typ", "T:a\n@:b.typ:B\n"),
+            ("sub/b.typ", "@:a.typ\n"),
+            ("outside.typ", "T:outside\n"),
+            ("sub/escape.typ", "@:../outside.typ\n"),
+        ]);
+        
+        let error = parse_script_file(&main).unwrap_err();
+        assert!(error.to_string().contains("include cycle"), "{}", error);
+        
+        let escape = dir.path().join("sub/escape.typ");
+        let error = parse_script_file(escape.to_str().unwrap()).unwrap_err();
+        assert!(error.to_string().contains("outside the script directory"), "{}", error);
+    }
+    
+    #[test]
+    fn test_recovering_parse_locates_errors_in_included_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let main = write_files(&dir, &[
+            ("main.typ", "*:START\n@:a.typ\n@:missing.typ\nT:after\n"),
+            ("a.typ", "T:fine\nbroken line\n"),
+        ]);
+        
+        let (script, errors) = parse_script_file_recovering(&main).unwrap();
+        
+        let locations: Vec<(String, usize)> = errors.iter().map(|e| match e {
+            ScriptError::Located { span, .. } => (Path::new(&span.file).file_name().unwrap().to_string_lossy().into_owned(), span.line),
+            other => panic!("unexpected error {}", other),
+        }).collect();
+        assert_eq!(locations, vec![("a.typ".to_string(), 2), ("main.typ".to_string(), 3)]);
+        assert_eq!(script.commands.len(), 3);
+    }
+}
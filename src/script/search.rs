@@ -0,0 +1,145 @@
+//! Lesson search path
+//!
+//! A lesson named on the command line is looked for the way the C
+//! implementation does it: as given (relative to the current directory),
+//! then in each directory of `$GTYPIST_PATH`, then in the installed data
+//! directory. A name without an extension also matches `<name>.typ`.
+
+use std::collections::HashSet;
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::script::{ScriptError, ScriptResult};
+
+/// Environment variable holding extra lesson directories, `:`-separated
+pub const LESSON_PATH_VAR: &str = "GTYPIST_PATH";
+
+/// Lesson run when none is named
+pub const DEFAULT_LESSON: &str = "gtypist.typ";
+
+/// File extensions of lessons, in every supported format
+const LESSON_EXTENSIONS: [&str; 3] = ["typ", "json", "toml"];
+
+/// Directory holding the lessons shipped with the program
+///
+/// Packagers set `GTYPIST_DATADIR` at build time (e.g. to
+/// `/usr/share/gtypist`); otherwise it is the `lessons` directory of the
+/// source tree the binary was built from.
+pub fn data_dir() -> PathBuf {
+    match option_env!("GTYPIST_DATADIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(env!("CARGO_MANIFEST_DIR")).join("lessons"),
+    }
+}
+
+/// Ordered list of directories lessons are looked up in
+#[derive(Debug, Clone, PartialEq)]
+pub struct LessonSearch {
+    pub dirs: Vec<PathBuf>,
+}
+
+impl LessonSearch {
+    /// Search path for the current environment
+    pub fn from_env() -> Self {
+        Self::new(env::var_os(LESSON_PATH_VAR).as_deref())
+    }
+
+    /// Search path for a given `$GTYPIST_PATH` value
+    pub fn new(gtypist_path: Option<&OsStr>) -> Self {
+        let mut dirs = vec![PathBuf::from(".")];
+        if let Some(gtypist_path) = gtypist_path {
+            dirs.extend(env::split_paths(gtypist_path).filter(|dir| !dir.as_os_str().is_empty()));
+        }
+        dirs.push(data_dir());
+        Self { dirs }
+    }
+
+    /// Every path `name` could refer to, in the order they are tried
+    pub fn candidates(&self, name: &str) -> Vec<PathBuf> {
+        let with_typ = Path::new(name).extension().is_none().then(|| format!("{}.typ", name));
+        let mut seen = HashSet::new();
+        self.dirs.iter()
+            .flat_map(|dir| std::iter::once(dir.join(name)).chain(with_typ.iter().map(|n| dir.join(n))))
+            .filter(|path| seen.insert(path.clone()))
+            .collect()
+    }
+
+    /// Find a lesson by name, reporting every path tried if it is missing
+    pub fn find(&self, name: &str) -> ScriptResult<PathBuf> {
+        let candidates = self.candidates(name);
+        match candidates.iter().find(|path| path.is_file()) {
+            Some(path) => Ok(path.clone()),
+            None => Err(ScriptError::LessonNotFound { name: name.to_string(), tried: candidates }),
+        }
+    }
+
+    /// Lesson files in each search directory, sorted by name
+    ///
+    /// Directories that don't exist or can't be read are left out.
+    pub fn lessons(&self) -> Vec<(PathBuf, Vec<PathBuf>)> {
+        let mut seen = HashSet::new();
+        self.dirs.iter()
+            .filter(|dir| seen.insert(dir.to_path_buf()))
+            .filter_map(|dir| {
+                let mut files: Vec<PathBuf> = fs::read_dir(dir).ok()?
+                    .filter_map(|entry| entry.ok().map(|e| e.path()))
+                    .filter(|path| path.is_file() && is_lesson(path))
+                    .collect();
+                files.sort();
+                Some((dir.clone(), files))
+            })
+            .collect()
+    }
+}
+
+fn is_lesson(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| LESSON_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_search_order() {
+        let search = LessonSearch::new(Some(OsStr::new("/one::/two")));
+        assert_eq!(search.dirs.len(), 4);
+        assert_eq!(search.dirs[0], PathBuf::from("."));
+        assert_eq!(search.dirs[1..3], [PathBuf::from("/one"), PathBuf::from("/two")]);
+        assert_eq!(search.dirs[3], data_dir());
+
+        assert_eq!(LessonSearch { dirs: vec![PathBuf::from("/one")] }.candidates("esp"),
+            vec![PathBuf::from("/one/esp"), PathBuf::from("/one/esp.typ")]);
+    }
+
+    #[test]
+    fn test_find_uses_first_match() {
+        let first = TempDir::new().unwrap();
+        let second = TempDir::new().unwrap();
+        fs::write(second.path().join("bar.typ"), "X:\n").unwrap();
+        fs::write(second.path().join("notes.txt"), "").unwrap();
+        let search = LessonSearch { dirs: vec![first.path().to_path_buf(), second.path().to_path_buf()] };
+
+        assert_eq!(search.find("bar").unwrap(), second.path().join("bar.typ"));
+
+        fs::write(first.path().join("bar.typ"), "X:\n").unwrap();
+        assert_eq!(search.find("bar.typ").unwrap(), first.path().join("bar.typ"));
+
+        let lessons = search.lessons();
+        assert_eq!(lessons[1], (second.path().to_path_buf(), vec![second.path().join("bar.typ")]));
+    }
+
+    #[test]
+    fn test_missing_lesson_lists_paths_tried() {
+        let dir = TempDir::new().unwrap();
+        let search = LessonSearch { dirs: vec![dir.path().to_path_buf()] };
+
+        let error = search.find("nothing.typ").unwrap_err();
+        assert!(matches!(&error, ScriptError::LessonNotFound { tried, .. } if tried.len() == 1));
+        assert!(error.to_string().contains(&dir.path().join("nothing.typ").display().to_string()));
+    }
+}
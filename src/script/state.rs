@@ -0,0 +1,221 @@
+//! Saved executor state
+//!
+//! When a lesson is left partway through, the executor's position and
+//! settings are saved so the next run of the same lesson can resume at
+//! the exact exercise. Saved states are keyed by the lesson's canonical
+//! path and carry a hash of the lesson's source files, so a state is never
+//! applied to a lesson that has changed since.
+
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::performance::ExerciseResult;
+use crate::script::{Script, ScriptError, ScriptResult};
+use crate::typing::TypingOptions;
+
+/// Everything needed to pick an executor up where it stopped
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExecutorState {
+    /// Canonical path of the lesson, as given by [`lesson_key`]
+    pub lesson: String,
+    /// [`content_hash`] of the lesson when the state was saved
+    pub content_hash: String,
+    pub position: usize,
+    pub error_percentage: f32,
+    pub default_error_percentage: f32,
+    pub error_percentage_cap: Option<f32>,
+    pub next_error_percentage: Option<f32>,
+    pub failure_label: Option<String>,
+    pub next_failure_label: Option<String>,
+    /// A one-shot `F:NULL` is pending
+    #[serde(default)]
+    pub next_failure_cleared: bool,
+    #[serde(default)]
+    pub typing_options: TypingOptions,
+    #[serde(default)]
+    pub next_typing_options: Option<TypingOptions>,
+    pub last_query_response: Option<bool>,
+    pub results: Vec<ExerciseResult>,
+    pub key_bindings: HashMap<u8, String>,
+    pub call_stack: Vec<usize>,
+    pub menu_stack: Vec<usize>,
+}
+
+/// Key a lesson's saved state is stored under
+pub fn lesson_key(path: &str) -> String {
+    Path::new(path).canonicalize()
+        .map_or_else(|_| path.to_string(), |p| p.to_string_lossy().into_owned())
+}
+
+/// Hash of every source file making up a script, includes too
+///
+/// Uses 64-bit FNV-1a, which is stable across builds and platforms.
+pub fn content_hash(script: &Script) -> ScriptResult<String> {
+    let mut files: Vec<&str> = vec![&script.path];
+    for span in &script.spans {
+        if !files.contains(&span.file.as_str()) {
+            files.push(&span.file);
+        }
+    }
+
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for file in files {
+        for byte in fs::read(file)? {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    Ok(format!("{:016x}", hash))
+}
+
+/// File of saved states, one per lesson
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateStore {
+    pub path: PathBuf,
+}
+
+impl StateStore {
+    /// Store at an explicit file path
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The user's store: `$XDG_STATE_HOME/gtypist/sessions.json`, falling
+    /// back to `~/.local/state/gtypist/sessions.json`
+    pub fn from_env() -> Option<Self> {
+        let state_home = env::var_os("XDG_STATE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))?;
+        Some(Self::at(state_home.join("gtypist").join("sessions.json")))
+    }
+
+    fn read(&self) -> ScriptResult<BTreeMap<String, ExecutorState>> {
+        match fs::read_to_string(&self.path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| ScriptError::InvalidFormat {
+                message: format!("{}: {}", self.path.display(), e),
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write(&self, states: &BTreeMap<String, ExecutorState>) -> ScriptResult<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(states)
+            .map_err(|e| ScriptError::InvalidFormat { message: e.to_string() })?;
+        fs::write(&self.path, json + "\n")?;
+        Ok(())
+    }
+
+    /// Saved state for a lesson, if there is one
+    pub fn load(&self, lesson_path: &str) -> ScriptResult<Option<ExecutorState>> {
+        Ok(self.read()?.remove(&lesson_key(lesson_path)))
+    }
+
+    /// Save a state, replacing any earlier one for the same lesson
+    pub fn save(&self, state: &ExecutorState) -> ScriptResult<()> {
+        let mut states = self.read()?;
+        states.insert(state.lesson.clone(), state.clone());
+        self.write(&states)
+    }
+
+    /// Forget a lesson's saved state
+    pub fn remove(&self, lesson_path: &str) -> ScriptResult<()> {
+        let mut states = self.read()?;
+        if states.remove(&lesson_key(lesson_path)).is_some() {
+            self.write(&states)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::executor::Executor;
+    use crate::terminal::MemoryBackend;
+    use tempfile::TempDir;
+
+    fn executor_for(path: &Path) -> Executor {
+        let script = Script::from_file(path.to_str().unwrap()).unwrap();
+        Executor::with_backend(script, Box::new(MemoryBackend::new()))
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let lesson = dir.path().join("lesson.typ");
+        fs::write(&lesson, "*:START\nE:10%*\nF:START*\nF:NULL\nK:3:START\nT:one\nT:two\n").unwrap();
+
+        let mut executor = executor_for(&lesson);
+        for _ in 0..5 {
+            executor.execute_next().unwrap();
+        }
+        executor.results.push(ExerciseResult::default());
+        let state = executor.state().unwrap();
+
+        let store = StateStore::at(dir.path().join("state/sessions.json"));
+        store.save(&state).unwrap();
+        let loaded = store.load(lesson.to_str().unwrap()).unwrap().unwrap();
+        assert_eq!(loaded, state);
+
+        let mut resumed = executor_for(&lesson);
+        resumed.resume(loaded).unwrap();
+        assert_eq!(resumed.script.position, 5);
+        assert_eq!(resumed.error_percentage, 10.0);
+        assert_eq!(resumed.failure_label.as_deref(), Some("START"));
+        assert_eq!(resumed.next_failure_label, Some(None));
+        assert_eq!(resumed.key_bindings[&3], "START");
+        assert_eq!(resumed.results.len(), 1);
+
+        store.remove(lesson.to_str().unwrap()).unwrap();
+        assert_eq!(store.load(lesson.to_str().unwrap()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_max_error_keeps_resumed_threshold() {
+        let dir = TempDir::new().unwrap();
+        let lesson = dir.path().join("lesson.typ");
+        fs::write(&lesson, "E:10%*\nT:one\n").unwrap();
+
+        let mut executor = executor_for(&lesson);
+        executor.execute_next().unwrap();
+        let state = executor.state().unwrap();
+
+        // A looser --max-error leaves the E:10%* in force, a stricter one
+        // lowers it
+        let mut resumed = executor_for(&lesson);
+        resumed.resume(state.clone()).unwrap();
+        resumed.set_max_error(20.0);
+        assert_eq!(resumed.error_percentage, 10.0);
+        assert_eq!(resumed.default_error_percentage, 20.0);
+
+        let mut resumed = executor_for(&lesson);
+        resumed.resume(state).unwrap();
+        resumed.set_max_error(5.0);
+        assert_eq!(resumed.error_percentage, 5.0);
+
+        // Still at the default, the session takes the new limit
+        let mut fresh = executor_for(&lesson);
+        fresh.set_max_error(20.0);
+        assert_eq!(fresh.error_percentage, 20.0);
+    }
+
+    #[test]
+    fn test_changed_lesson_is_not_resumed() {
+        let dir = TempDir::new().unwrap();
+        let lesson = dir.path().join("lesson.typ");
+        fs::write(&lesson, "T:one\nT:two\n").unwrap();
+        let state = executor_for(&lesson).state().unwrap();
+
+        fs::write(&lesson, "T:one\nT:changed\n").unwrap();
+        let mut executor = executor_for(&lesson);
+        assert!(executor.resume(state).is_err());
+        assert_eq!(executor.script.position, 0);
+    }
+}
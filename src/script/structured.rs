@@ -0,0 +1,211 @@
+//! Structured lesson formats
+//!
+//! Lessons can also be written as JSON or TOML documents holding the
+//! command list, which is easier to generate from other tools than the
+//! column-sensitive script syntax. Each command is a table tagged with
+//! its `type`:
+//!
+//! ```toml
+//! [[commands]]
+//! type = "label"
+//! name = "START"
+//!
+//! [[commands]]
+//! type = "drill"
+//! text = "asdf jkl;"
+//! practice_only = false
+//! ```
+//!
+//! Labels and navigation behave exactly as in a `.typ` file, and leading
+//! `comment` commands carry the same metadata header.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::script::{Script, ScriptError, ScriptResult, SourceSpan};
+use crate::script::commands::Command;
+use crate::script::encoding::is_charset_directive;
+use crate::script::metadata::LessonMetadata;
+
+/// File formats a lesson can be stored in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LessonFormat {
+    /// Classic GNU Typist script syntax
+    Typ,
+    Json,
+    Toml,
+}
+
+impl LessonFormat {
+    /// Pick a format from a file extension, defaulting to `.typ` syntax
+    pub fn from_path(path: &str) -> Self {
+        let extension = Path::new(path).extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("json") => LessonFormat::Json,
+            Some("toml") => LessonFormat::Toml,
+            _ => LessonFormat::Typ,
+        }
+    }
+
+    /// Look up a format by name (`typ`, `json` or `toml`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "typ" => Some(LessonFormat::Typ),
+            "json" => Some(LessonFormat::Json),
+            "toml" => Some(LessonFormat::Toml),
+            _ => None,
+        }
+    }
+}
+
+/// On-disk layout of a structured lesson
+#[derive(Debug, Serialize, Deserialize)]
+struct LessonDocument {
+    commands: Vec<Command>,
+}
+
+/// Parse a JSON or TOML lesson file
+///
+/// These formats have no meaningful source lines, so each command's span
+/// gives its 1-based position in the `commands` list as the line.
+pub fn parse_structured_file(path: &str, format: LessonFormat) -> ScriptResult<Script> {
+    let content = fs::read_to_string(path)?;
+    let invalid = |message: String| ScriptError::InvalidFormat {
+        message: format!("{}: {}", path, message),
+    };
+
+    let document: LessonDocument = match format {
+        LessonFormat::Json => serde_json::from_str(&content).map_err(|e| invalid(e.to_string()))?,
+        LessonFormat::Toml => toml::from_str(&content).map_err(|e| invalid(e.to_string()))?,
+        LessonFormat::Typ => return Err(invalid("not a structured lesson format".to_string())),
+    };
+
+    let mut labels = HashMap::new();
+    let mut spans = Vec::new();
+    for (index, command) in document.commands.iter().enumerate() {
+        if let Command::Label { name } = command {
+            labels.insert(name.clone(), index);
+        }
+        spans.push(SourceSpan {
+            file: path.to_string(),
+            line: index + 1,
+            end_line: index + 1,
+            column: 1,
+            end_column: 1,
+        });
+    }
+
+    Ok(Script {
+        path: path.to_string(),
+        metadata: LessonMetadata::from_commands(&document.commands),
+        commands: document.commands,
+        labels,
+        spans,
+        position: 0,
+    })
+}
+
+/// Write a script's commands in the given format
+pub fn write_lesson(script: &Script, format: LessonFormat) -> ScriptResult<String> {
+    // Like .typ output, these are UTF-8 whatever the lesson was read from
+    let commands = script.commands.iter()
+        .filter(|command| !matches!(command, Command::Comment { content } if is_charset_directive(content)))
+        .cloned()
+        .collect();
+    let document = LessonDocument { commands };
+    let invalid = |message: String| ScriptError::InvalidFormat { message };
+
+    match format {
+        LessonFormat::Typ => script.to_typ(),
+        LessonFormat::Json => serde_json::to_string_pretty(&document)
+            .map(|json| json + "\n")
+            .map_err(|e| invalid(e.to_string())),
+        LessonFormat::Toml => toml::to_string_pretty(&document).map_err(|e| invalid(e.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::Builder;
+
+    fn write_temp(suffix: &str, content: &str) -> tempfile::NamedTempFile {
+        let mut temp_file = Builder::new().suffix(suffix).tempfile().unwrap();
+        temp_file.write_all(content.as_bytes()).unwrap();
+        temp_file
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(LessonFormat::from_path("a/b.json"), LessonFormat::Json);
+        assert_eq!(LessonFormat::from_path("b.TOML"), LessonFormat::Toml);
+        assert_eq!(LessonFormat::from_path("b.typ"), LessonFormat::Typ);
+        assert_eq!(LessonFormat::from_path("noext"), LessonFormat::Typ);
+    }
+
+    #[test]
+    fn test_parse_json_lesson() {
+        let temp_file = write_temp(".json", r#"{"commands": [
+            {"type": "label", "name": "START"},
+            {"type": "drill", "text": "abc", "practice_only": true},
+            {"type": "goto", "label": "START"},
+            {"type": "exit"}
+        ]}"#);
+
+        let script = Script::from_file(temp_file.path().to_str().unwrap()).unwrap();
+
+        assert_eq!(script.commands.len(), 4);
+        assert_eq!(script.labels["START"], 0);
+        assert_eq!(script.commands[1], Command::Drill { text: "abc".to_string(), practice_only: true });
+        assert_eq!(script.spans[2].line, 3);
+    }
+
+    #[test]
+    fn test_parse_toml_lesson() {
+        let temp_file = write_temp(".toml", r#"
+[[commands]]
+type = "key_bind"
+key = 12
+
+[[commands]]
+type = "menu"
+title = "Main"
+items = [{ label = "END", description = "Quit" }]
+
+[[commands]]
+type = "label"
+name = "END"
+"#);
+
+        let script = Script::from_file(temp_file.path().to_str().unwrap()).unwrap();
+
+        assert_eq!(script.commands[0], Command::KeyBind { key: 12, label: None });
+        assert!(matches!(&script.commands[1], Command::Menu { items, .. } if items[0].label == "END"));
+        assert_eq!(script.labels["END"], 2);
+    }
+
+    #[test]
+    fn test_invalid_document_is_reported() {
+        let temp_file = write_temp(".json", r#"{"commands": [{"type": "nope"}]}"#);
+        let error = Script::from_file(temp_file.path().to_str().unwrap()).unwrap_err();
+        assert!(matches!(error, ScriptError::InvalidFormat { .. }));
+    }
+
+    #[test]
+    fn test_formats_round_trip() {
+        let lesson = write_temp(".typ", "*:START\nB:Banner\nT:Some text\nE:4%\nM: \"Menu\"\n :START  \"Again\"\nX:\n");
+        let script = Script::from_file(lesson.path().to_str().unwrap()).unwrap();
+
+        for (format, suffix) in [(LessonFormat::Json, ".json"), (LessonFormat::Toml, ".toml"), (LessonFormat::Typ, ".typ")] {
+            let written = write_lesson(&script, format).unwrap();
+            let converted = write_temp(suffix, &written);
+            let reparsed = Script::from_file(converted.path().to_str().unwrap()).unwrap();
+            assert_eq!(reparsed.commands, script.commands, "{:?}", format);
+            assert_eq!(reparsed.labels, script.labels, "{:?}", format);
+        }
+    }
+}
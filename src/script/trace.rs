@@ -0,0 +1,198 @@
+//! Execution trace
+//!
+//! An executor given a [`TraceSink`] writes one JSON object per line for
+//! every command it runs, every jump it takes (and why) and every
+//! exercise outcome, e.g.
+//!
+//! ```text
+//! {"time":"2024-05-01T10:00:00.000Z","event":"command","index":3,"span":{...},"command":{"type":"drill",...}}
+//! {"time":"2024-05-01T10:00:09.500Z","event":"outcome","index":3,"exercise":"drill","outcome":{"failed":{...}}}
+//! {"time":"2024-05-01T10:00:09.500Z","event":"jump","from":3,"to":0,"label":"START","cause":"failure"}
+//! ```
+
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use crate::exercises::ExerciseOutcome;
+use crate::script::{ScriptError, ScriptResult, SourceSpan};
+use crate::script::commands::Command;
+
+/// Exercise families, which differ in how their outcomes are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExerciseKind {
+    Tutorial,
+    Drill,
+    SpeedTest,
+}
+
+/// Why execution jumped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JumpCause {
+    /// G:
+    Goto,
+    /// Y: after a yes answer
+    Yes,
+    /// N: after a no answer
+    No,
+    /// J: condition held
+    Performance,
+    /// C: subroutine call
+    Call,
+    /// R: back to the caller
+    Return,
+    /// F: label after a failed drill
+    Failure,
+    /// Item chosen from an M: menu, or back to the menu after a failed drill
+    Menu,
+    /// Escape from a menu, to its UP= target or the previous menu
+    MenuUp,
+    /// The same exercise again, after Ctrl+R or a failed drill
+    Repeat,
+    /// Function key bound with K:
+    KeyBinding,
+}
+
+impl JumpCause {
+    /// Cause of a jump made by the command itself, if it can only have one
+    pub fn of(command: &Command) -> Option<Self> {
+        match command {
+            Command::Goto { .. } => Some(JumpCause::Goto),
+            Command::YesGoto { .. } => Some(JumpCause::Yes),
+            Command::NoGoto { .. } => Some(JumpCause::No),
+            Command::PerformanceGoto { .. } => Some(JumpCause::Performance),
+            Command::Call { .. } => Some(JumpCause::Call),
+            Command::Return => Some(JumpCause::Return),
+            Command::Menu { .. } => Some(JumpCause::Menu),
+            _ => None,
+        }
+    }
+}
+
+/// Something that happened while running a lesson
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TraceEvent {
+    /// A command is about to run
+    Command {
+        index: usize,
+        span: Option<SourceSpan>,
+        command: Command,
+    },
+    /// Execution moved somewhere other than the next command
+    Jump {
+        from: usize,
+        to: usize,
+        /// Label jumped to; none for returns and going back a menu
+        label: Option<String>,
+        cause: JumpCause,
+    },
+    /// An exercise finished
+    Outcome {
+        index: usize,
+        exercise: ExerciseKind,
+        outcome: ExerciseOutcome,
+    },
+}
+
+/// One line of a trace file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceRecord {
+    /// UTC time the event was recorded, RFC 3339
+    pub time: String,
+    #[serde(flatten)]
+    pub event: TraceEvent,
+}
+
+/// Destination of an executor's trace
+pub struct TraceSink {
+    out: Box<dyn Write>,
+}
+
+impl TraceSink {
+    /// Trace to any writer
+    pub fn new(out: impl Write + 'static) -> Self {
+        Self { out: Box::new(out) }
+    }
+
+    /// Trace to a file, replacing it if it exists
+    pub fn create(path: &str) -> ScriptResult<Self> {
+        let file = File::create(path)
+            .map_err(|e| ScriptError::FileError(format!("Cannot create trace file '{}': {}", path, e)))?;
+        Ok(Self::new(file))
+    }
+
+    /// Write one event as a line, flushed so the trace survives a crash
+    pub fn record(&mut self, event: TraceEvent) -> ScriptResult<()> {
+        let record = TraceRecord {
+            time: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            event,
+        };
+        let line = serde_json::to_string(&record)
+            .map_err(|e| ScriptError::InvalidFormat { message: e.to_string() })?;
+        writeln!(self.out, "{}", line)?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Read back a trace file written by a [`TraceSink`]
+pub fn read_trace(path: &str) -> ScriptResult<Vec<TraceRecord>> {
+    let reader = BufReader::new(fs::File::open(path)?);
+    let mut records = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        records.push(serde_json::from_str(&line).map_err(|e| ScriptError::InvalidFormat {
+            message: format!("{}:{}: {}", path, number + 1, e),
+        })?);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner;
+    use crate::script::Script;
+    use crate::script::executor::Executor;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_trace_records_commands_jumps_and_outcomes() {
+        let dir = TempDir::new().unwrap();
+        let lesson = dir.path().join("lesson.typ");
+        fs::write(&lesson, "*:START\nF:START\nD:ab\nQ:Again?\nN:END\nG:START\n*:END\nX:\n").unwrap();
+        let trace_path = dir.path().join("trace.jsonl");
+        let trace_path = trace_path.to_str().unwrap();
+
+        // Fail the drill once, pass it, then answer no
+        let keys = "0 x\n100 y\n200 <Enter>\n300 a\n400 b\n500 <Enter>\n600 n\n";
+        let events = runner::parse_key_events(keys).unwrap();
+        let script = Script::from_file(lesson.to_str().unwrap()).unwrap();
+        let mut executor = Executor::with_backend(script, Box::new(runner::backend_from_events(events)))
+            .with_trace(TraceSink::create(trace_path).unwrap());
+        runner::run(&mut executor).unwrap();
+
+        let events: Vec<TraceEvent> = read_trace(trace_path).unwrap().into_iter().map(|r| r.event).collect();
+        let jumps: Vec<(usize, usize, JumpCause)> = events.iter().filter_map(|event| match event {
+            TraceEvent::Jump { from, to, cause, .. } => Some((*from, *to, *cause)),
+            _ => None,
+        }).collect();
+        assert_eq!(jumps, vec![(2, 0, JumpCause::Failure), (4, 6, JumpCause::No)]);
+
+        let outcomes: Vec<&ExerciseOutcome> = events.iter().filter_map(|event| match event {
+            TraceEvent::Outcome { outcome, exercise: ExerciseKind::Drill, .. } => Some(outcome),
+            _ => None,
+        }).collect();
+        assert!(matches!(outcomes[0], ExerciseOutcome::Failed(result) if result.errors == 2));
+        assert!(matches!(outcomes[1], ExerciseOutcome::Completed(result) if result.errors == 0));
+
+        assert!(matches!(&events[0], TraceEvent::Command { index: 0, span: Some(span), .. } if span.line == 1));
+        assert!(matches!(events.last(), Some(TraceEvent::Command { command: Command::Exit, .. })));
+    }
+}
@@ -0,0 +1,356 @@
+//! Script file writer
+//!
+//! Turns parsed commands back into canonical lesson script text that both
+//! this port and the C implementation can load. Parsing the output gives
+//! the same commands again. Commands spliced in by an include are written
+//! inline, since the C implementation has no include directive.
+
+use crate::script::{Script, ScriptError, ScriptResult};
+use crate::script::commands::{chars, Command};
+use crate::script::encoding::is_charset_directive;
+
+/// Tutorial and instruction text is wrapped onto continuation lines at
+/// roughly this many columns
+const TEXT_WIDTH: usize = 72;
+
+/// Write a whole script as lesson script text, one command per line
+///
+/// The text is UTF-8 whatever the lesson was read from, so `#@charset:`
+/// lines are left out rather than left to misdescribe it.
+pub fn write_script(script: &Script) -> ScriptResult<String> {
+    let mut output = String::new();
+    for command in &script.commands {
+        if matches!(command, Command::Comment { content } if is_charset_directive(content)) {
+            continue;
+        }
+        output.push_str(&write_command(command)?);
+    }
+    Ok(output)
+}
+
+/// Write a single command, including any continuation lines
+///
+/// The result always ends with a newline. Commands holding text the
+/// script syntax cannot express (such as trailing whitespace, which the
+/// parser trims) are rejected rather than silently changed.
+pub fn write_command(command: &Command) -> ScriptResult<String> {
+    let lines = match command {
+        Command::Comment { content } => {
+            if !content.starts_with(chars::COMMENT) && !content.starts_with(chars::ALT_COMMENT) {
+                return Err(unrepresentable(command, "comments must start with '#' or '!'"));
+            }
+            vec![single_line(command, content)?.to_string()]
+        },
+        Command::Label { name } => vec![line(chars::LABEL, single_line(command, name)?)],
+        Command::Tutorial { text } => wrapped(command, chars::TUTORIAL, text)?,
+        Command::Instruction { text } => wrapped(command, chars::INSTRUCTION, text)?,
+        Command::Clear { banner } => {
+            let banner = match banner {
+                Some(banner) if banner.is_empty() => {
+                    return Err(unrepresentable(command, "an empty banner is read back as no banner"));
+                },
+                Some(banner) => single_line(command, banner)?,
+                None => "",
+            };
+            vec![line(chars::CLEAR, banner)]
+        },
+        Command::Goto { label } => vec![line(chars::GOTO, single_line(command, label)?)],
+        Command::Exit => vec![line(chars::EXIT, "")],
+        Command::Call { label } => vec![line(chars::CALL, single_line(command, label)?)],
+        Command::Return => vec![line(chars::RETURN, "")],
+        Command::Query { text } => vec![line(chars::QUERY, single_line(command, text)?)],
+        Command::YesGoto { label } => vec![line(chars::YES_GOTO, single_line(command, label)?)],
+        Command::NoGoto { label } => vec![line(chars::NO_GOTO, single_line(command, label)?)],
+        Command::PerformanceGoto { condition, label } => {
+            if label.trim() != label || label.is_empty() {
+                return Err(unrepresentable(command, "jump label cannot be read back"));
+            }
+            vec![line(chars::PERFORMANCE_GOTO, &format!("{}{}{}", condition, chars::SEP, single_line(command, label)?))]
+        },
+        Command::Drill { text, practice_only } => {
+            let command_char = if *practice_only { chars::DRILL_PRACTICE_ONLY } else { chars::DRILL };
+            multi_line(command, command_char, text)?
+        },
+        Command::SpeedTest { text, practice_only } => {
+            let command_char = if *practice_only { chars::SPEEDTEST_PRACTICE_ONLY } else { chars::SPEEDTEST };
+            multi_line(command, command_char, text)?
+        },
+        Command::TutorialFile { path } => vec![line(chars::TUTORIAL_FILE, single_line(command, path)?)],
+        Command::DrillFile { path, practice_only } => {
+            let command_char = if *practice_only { chars::DRILL_FILE_PRACTICE } else { chars::DRILL_FILE };
+            vec![line(command_char, single_line(command, path)?)]
+        },
+        Command::SpeedTestFile { path, practice_only } => {
+            let command_char = if *practice_only { chars::SPEEDTEST_FILE_PRACTICE } else { chars::SPEEDTEST_FILE };
+            vec![line(command_char, single_line(command, path)?)]
+        },
+        Command::KeyBind { key, label } => {
+            let label = match label {
+                Some(label) if label == "NULL" || label.trim() != label || label.is_empty() => {
+                    return Err(unrepresentable(command, "key binding label cannot be read back"));
+                },
+                Some(label) => single_line(command, label)?,
+                None => "NULL",
+            };
+            vec![line(chars::KEYBIND, &format!("{}{}{}", key, chars::SEP, label))]
+        },
+        Command::ErrorMaxSet { percentage, persistent } => {
+            let value = match percentage {
+                Some(percentage) => format!("{}%", percentage),
+                None => "default".to_string(),
+            };
+            vec![line(chars::ERROR_MAX_SET, &with_marker(value, *persistent))]
+        },
+        Command::OnFailureSet { label, persistent } => {
+            let label = match label {
+                Some(label) if label == "NULL" || label.trim() != label || label.is_empty() || label.ends_with('*') => {
+                    return Err(unrepresentable(command, "failure label cannot be read back"));
+                },
+                Some(label) => single_line(command, label)?,
+                None => "NULL",
+            };
+            vec![line(chars::ON_FAILURE_SET, &with_marker(label.to_string(), *persistent))]
+        },
+        Command::OptionSet { options, persistent } => {
+            if options.is_empty() {
+                return Err(unrepresentable(command, "O: needs at least one option"));
+            }
+            let options: Vec<String> = options.iter().map(ToString::to_string).collect();
+            vec![line(chars::OPTION_SET, &with_marker(options.join(" "), *persistent))]
+        },
+        Command::Menu { title, up, items } => {
+            let up = match up {
+                Some(up) if up.is_empty() || up.contains(char::is_whitespace) => {
+                    return Err(unrepresentable(command, "UP= target cannot be read back"));
+                },
+                Some(up) => format!(" UP={}", up),
+                None => String::new(),
+            };
+            let mut lines = vec![line(chars::MENU, &format!("{} \"{}\"", up, quoted(command, title)?))];
+            for item in items {
+                let label = single_line(command, &item.label)?;
+                let description = quoted(command, &item.description)?;
+                if label.trim() != label || label.is_empty() || label.contains('"') || description.contains('"') {
+                    return Err(unrepresentable(command, "menu item cannot be read back"));
+                }
+                lines.push(continuation(&format!("{}  \"{}\"", label, description)));
+            }
+            lines
+        },
+    };
+
+    let mut output = lines.join("\n");
+    output.push('\n');
+    Ok(output)
+}
+
+/// A command line: command character, separator and data
+fn line(command_char: char, data: &str) -> String {
+    format!("{}{}{}", command_char, chars::SEP, data)
+}
+
+/// Append the `*` persistence marker used by E: and F:
+fn with_marker(mut data: String, persistent: bool) -> String {
+    if persistent {
+        data.push('*');
+    }
+    data
+}
+
+/// A continuation line carrying `data`
+fn continuation(data: &str) -> String {
+    format!("{}{}{}", chars::CONT, chars::SEP, data)
+}
+
+/// Check that text fits on one line without losing anything to trimming
+fn single_line<'a>(command: &Command, text: &'a str) -> ScriptResult<&'a str> {
+    quoted(command, text)?;
+    if text.trim_end() != text {
+        return Err(unrepresentable(command, "text ends with whitespace"));
+    }
+    Ok(text)
+}
+
+/// Check that quoted text fits on one line; quotes keep its whitespace
+fn quoted<'a>(command: &Command, text: &'a str) -> ScriptResult<&'a str> {
+    if text.contains(['\n', '\r']) {
+        return Err(unrepresentable(command, "text contains a line break"));
+    }
+    Ok(text)
+}
+
+/// Drill and speed test text, one continuation line per text line
+fn multi_line(command: &Command, command_char: char, text: &str) -> ScriptResult<Vec<String>> {
+    let mut lines = Vec::new();
+    for (index, text_line) in text.split('\n').enumerate() {
+        if index == 0 {
+            lines.push(line(command_char, single_line(command, text_line)?));
+        } else {
+            // Continuation lines keep their text exactly, blank or not
+            lines.push(continuation(quoted(command, text_line)?));
+        }
+    }
+    Ok(lines)
+}
+
+/// Tutorial and instruction text, wrapped at single spaces
+///
+/// The parser joins continuation lines with one space and trims each
+/// line, so only spaces with non-whitespace on both sides can become
+/// line breaks.
+fn wrapped(command: &Command, command_char: char, text: &str) -> ScriptResult<Vec<String>> {
+    let text = single_line(command, text)?;
+    let chars: Vec<char> = text.chars().collect();
+
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let mut last_break = None;
+    for (index, &ch) in chars.iter().enumerate() {
+        let breakable = ch == ' '
+            && index > start
+            && !chars[index - 1].is_whitespace()
+            && chars.get(index + 1).is_some_and(|next| !next.is_whitespace());
+        if breakable {
+            if index - start > TEXT_WIDTH {
+                if let Some(at) = last_break {
+                    lines.push(chars[start..at].iter().collect::<String>());
+                    start = at + 1;
+                }
+            }
+            last_break = Some(index).filter(|&at| at > start);
+        }
+    }
+    if chars.len() - start > TEXT_WIDTH {
+        if let Some(at) = last_break {
+            lines.push(chars[start..at].iter().collect::<String>());
+            start = at + 1;
+        }
+    }
+    lines.push(chars[start..].iter().collect::<String>());
+
+    Ok(lines.iter().enumerate()
+        .map(|(index, text_line)| if index == 0 { line(command_char, text_line) } else { continuation(text_line) })
+        .collect())
+}
+
+fn unrepresentable(command: &Command, reason: &str) -> ScriptError {
+    ScriptError::InvalidFormat {
+        message: format!("cannot write {:?} as script text: {}", command, reason),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::commands::{Comparison, Condition, MenuItem, Metric, OptionSetting, TypingOption};
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn reparse(text: &str) -> Script {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(text.as_bytes()).unwrap();
+        Script::from_file(temp_file.path().to_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_write_commands() {
+        let commands = vec![
+            Command::Comment { content: "# lesson".to_string() },
+            Command::Label { name: "START".to_string() },
+            Command::Clear { banner: Some("Banner".to_string()) },
+            Command::Drill { text: "asdf\n  jkl;".to_string(), practice_only: true },
+            Command::KeyBind { key: 12, label: None },
+            Command::Call { label: "START".to_string() },
+            Command::Return,
+            Command::ErrorMaxSet { percentage: Some(2.5), persistent: false },
+            Command::ErrorMaxSet { percentage: None, persistent: true },
+            Command::OnFailureSet { label: Some("START".to_string()), persistent: true },
+            Command::OnFailureSet { label: None, persistent: false },
+            Command::OptionSet {
+                options: vec![OptionSetting { option: TypingOption::WordProcessor, enabled: false }],
+                persistent: true,
+            },
+            Command::PerformanceGoto {
+                condition: Condition { metric: Metric::Accuracy, comparison: Comparison::GreaterOrEqual, value: 97.5 },
+                label: "START".to_string(),
+            },
+            Command::Menu {
+                title: "Main".to_string(),
+                up: None,
+                items: vec![MenuItem { label: "START".to_string(), description: "Again".to_string() }],
+            },
+            Command::Menu { title: "Sub".to_string(), up: Some("START".to_string()), items: Vec::new() },
+            Command::Exit,
+        ];
+        let text: String = commands.iter().map(|c| write_command(c).unwrap()).collect();
+
+        assert_eq!(text, "# lesson\n*:START\nB:Banner\nd:asdf\n :  jkl;\nK:12:NULL\nC:START\nR:\nE:2.5%\nE:default*\nF:START*\nF:NULL\nO:no-word-processor*\nJ:accuracy >= 97.5:START\nM: \"Main\"\n :START  \"Again\"\nM: UP=START \"Sub\"\nX:\n");
+        assert_eq!(reparse(&text).commands, commands);
+    }
+
+    #[test]
+    fn test_long_tutorial_text_wraps() {
+        let text = format!("{} end  of  text", "word ".repeat(30).trim_end());
+        let command = Command::Tutorial { text };
+        let written = write_command(&command).unwrap();
+
+        assert!(written.lines().count() > 1);
+        assert!(written.lines().all(|l| l.chars().count() <= TEXT_WIDTH + 2));
+        assert_eq!(reparse(&written).commands, vec![command]);
+    }
+
+    #[test]
+    fn test_drill_blank_lines_and_spaces_round_trip() {
+        let command = Command::Drill { text: "ab\n\n  cd  \n".to_string(), practice_only: false };
+        let written = write_command(&command).unwrap();
+
+        assert_eq!(written, "D:ab\n :\n :  cd  \n :\n");
+        assert_eq!(reparse(&written).commands, vec![command]);
+    }
+
+    #[test]
+    fn test_rejects_unrepresentable_text() {
+        assert!(write_command(&Command::Tutorial { text: "two\nlines".to_string() }).is_err());
+        assert!(write_command(&Command::Drill { text: "a \nb".to_string(), practice_only: false }).is_err());
+        assert!(write_command(&Command::Goto { label: "END ".to_string() }).is_err());
+        assert!(write_command(&Command::Comment { content: "no marker".to_string() }).is_err());
+        assert!(write_command(&Command::KeyBind { key: 1, label: Some("NULL".to_string()) }).is_err());
+        assert!(write_command(&Command::OnFailureSet { label: Some("A*".to_string()), persistent: false }).is_err());
+    }
+
+    #[test]
+    fn test_legacy_charset_lesson_round_trips_as_utf8() {
+        let source = "# Russian\n#@charset: koi8-r\nD:Привет\nX:\n";
+        let (bytes, _, _) = encoding_rs::KOI8_R.encode(source);
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(&bytes).unwrap();
+        let script = Script::from_file(temp_file.path().to_str().unwrap()).unwrap();
+
+        let written = write_script(&script).unwrap();
+        assert_eq!(written, "# Russian\nD:Привет\nX:\n");
+        let reparsed = reparse(&written);
+        assert_eq!(reparsed.commands[1], Command::Drill { text: "Привет".to_string(), practice_only: false });
+        assert_eq!(write_script(&reparsed).unwrap(), written);
+    }
+
+    #[test]
+    fn test_lessons_round_trip() {
+        let mut checked = 0;
+        for entry in std::fs::read_dir("lessons").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|e| e.to_str()) != Some("typ") {
+                continue;
+            }
+            let script = Script::from_file(path.to_str().unwrap())
+                .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+
+            let written = write_script(&script).unwrap();
+            let reparsed = reparse(&written);
+            assert_eq!(reparsed.commands, script.commands, "{}", path.display());
+            assert_eq!(reparsed.labels, script.labels, "{}", path.display());
+            assert_eq!(write_script(&reparsed).unwrap(), written, "{}", path.display());
+            checked += 1;
+        }
+        assert!(checked > 0);
+    }
+}
@@ -0,0 +1,324 @@
+//! Terminal backend abstraction
+//!
+//! Exercises, menus and the script executor talk to the user through a
+//! `Backend`: an input source for key events plus a render target for
+//! output. `CrosstermBackend` drives the real terminal, while
+//! `MemoryBackend` replays queued events and captures everything written
+//! so that whole lessons can run without a TTY.
+
+use std::collections::VecDeque;
+use std::io::{self, stdout, Stdout, Write};
+use std::time::{Duration, Instant};
+use crossterm::{
+    cursor, QueueableCommand,
+    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+    terminal::{Clear, ClearType},
+};
+
+/// Input source and render target used by all interactive screens
+pub trait Backend: Write {
+    /// Block until the next input event is available
+    fn read_event(&mut self) -> io::Result<Event>;
+
+    /// Current screen size as (columns, rows)
+    fn size(&self) -> (u16, u16);
+
+    /// Current time as seen by this backend, used for all exercise timing
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    /// Clear the screen and move the cursor to the top-left corner
+    fn clear_screen(&mut self) -> io::Result<()> {
+        self.queue(Clear(ClearType::All))?.queue(cursor::MoveTo(0, 0))?;
+        Ok(())
+    }
+}
+
+/// Backend for the real terminal, using crossterm for input
+pub struct CrosstermBackend {
+    stdout: Stdout,
+}
+
+impl CrosstermBackend {
+    pub fn new() -> Self {
+        Self { stdout: stdout() }
+    }
+}
+
+impl Default for CrosstermBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for CrosstermBackend {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stdout.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdout.flush()
+    }
+}
+
+impl Backend for CrosstermBackend {
+    fn read_event(&mut self) -> io::Result<Event> {
+        event::read()
+    }
+
+    fn size(&self) -> (u16, u16) {
+        match crossterm::terminal::size() {
+            Ok(size) => size,
+            Err(_) => {
+                // If we can't get terminal size, try environment variables or use 80
+                let columns = std::env::var("COLUMNS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(80);
+                (columns, 24)
+            }
+        }
+    }
+}
+
+/// In-memory backend that replays queued events and records output
+///
+/// Every event carries a timestamp relative to the backend's creation.
+/// Reading an event advances the backend clock to that timestamp, so
+/// exercise timing is driven entirely by the queued input.
+#[derive(Debug, Clone)]
+pub struct MemoryBackend {
+    events: VecDeque<(Duration, Event)>,
+    output: Vec<u8>,
+    width: u16,
+    height: u16,
+    epoch: Instant,
+    clock: Duration,
+}
+
+impl MemoryBackend {
+    /// Create an 80x24 backend with no pending input
+    pub fn new() -> Self {
+        Self {
+            events: VecDeque::new(),
+            output: Vec::new(),
+            width: 80,
+            height: 24,
+            epoch: Instant::now(),
+            clock: Duration::ZERO,
+        }
+    }
+
+    /// Create a backend with a custom screen size
+    pub fn with_size(width: u16, height: u16) -> Self {
+        Self { width, height, ..Self::new() }
+    }
+
+    /// Queue an arbitrary input event at the timestamp of the previous one
+    pub fn push_event(&mut self, event: Event) {
+        let at = self.events.back().map_or(self.clock, |(at, _)| *at);
+        self.events.push_back((at, event));
+    }
+
+    /// Queue an input event that arrives `at` after the backend was created
+    pub fn push_event_at(&mut self, at: Duration, event: Event) {
+        self.events.push_back((at, event));
+    }
+
+    /// Queue a key press without modifiers
+    pub fn push_key(&mut self, code: KeyCode) {
+        self.push_event(Event::Key(KeyEvent::new(code, KeyModifiers::NONE)));
+    }
+
+    /// Queue one key press per character of `text`
+    pub fn push_text(&mut self, text: &str) {
+        for ch in text.chars() {
+            self.push_key(KeyCode::Char(ch));
+        }
+    }
+
+    /// Number of queued events not yet consumed
+    pub fn pending_events(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Everything written so far, including escape sequences
+    pub fn output(&self) -> String {
+        String::from_utf8_lossy(&self.output).into_owned()
+    }
+
+    /// Everything written so far with ANSI escape sequences removed
+    pub fn plain_output(&self) -> String {
+        strip_ansi(&self.output())
+    }
+
+    /// Discard recorded output
+    pub fn clear_output(&mut self) {
+        self.output.clear();
+    }
+}
+
+impl Default for MemoryBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for MemoryBackend {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn read_event(&mut self) -> io::Result<Event> {
+        let (at, event) = self.events.pop_front().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "no more input events")
+        })?;
+        self.clock = self.clock.max(at);
+        Ok(event)
+    }
+
+    fn size(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
+
+    fn now(&self) -> Instant {
+        self.epoch + self.clock
+    }
+}
+
+/// Remove CSI escape sequences (ESC '[' ... final byte) from text
+fn strip_ansi(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if ch == '\x1B' && chars.peek() == Some(&'[') {
+            chars.next();
+            // Parameters and intermediates run until a byte in '@'..='~'
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        } else {
+            result.push(ch);
+        }
+    }
+
+    result
+}
+
+/// Center text within the given width
+pub fn center_text(text: &str, width: usize) -> String {
+    let text_len = text.chars().count(); // Use char count for proper Unicode handling
+    if text_len >= width {
+        return text.to_string(); // Don't try to center if text is too long
+    }
+
+    let padding = (width - text_len) / 2;
+    format!("{}{}", " ".repeat(padding), text)
+}
+
+/// Write a line starting at column 1
+///
+/// Raw mode does not return the cursor on newline, so every line is
+/// prefixed with an explicit move to the first column.
+pub fn write_line(out: &mut dyn Backend, text: &str) -> io::Result<()> {
+    write!(out, "\x1B[1G{}\r\n", text)
+}
+
+/// Write a line centered on the current screen width
+pub fn write_centered(out: &mut dyn Backend, text: &str) -> io::Result<()> {
+    let width = out.size().0 as usize;
+    write_line(out, &center_text(text, width))
+}
+
+/// Write text with word wrapping, each line at the left margin
+pub fn write_wrapped_text(out: &mut dyn Backend, text: &str) -> io::Result<()> {
+    const LINE_WIDTH: usize = 50;
+
+    // Split text into words and wrap them
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let mut current_line = String::new();
+
+    for word in words {
+        // If adding this word would exceed the line width, print current line and start new one
+        if !current_line.is_empty() && current_line.len() + 1 + word.len() > LINE_WIDTH {
+            write_line(out, &current_line)?;
+            current_line.clear();
+        }
+
+        // Add word to current line
+        if !current_line.is_empty() {
+            current_line.push(' ');
+        }
+        current_line.push_str(word);
+    }
+
+    // Print any remaining text
+    if !current_line.is_empty() {
+        write_line(out, &current_line)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_backend_replays_events() {
+        let mut backend = MemoryBackend::new();
+        backend.push_text("ab");
+        backend.push_key(KeyCode::Esc);
+
+        assert_eq!(backend.pending_events(), 3);
+        assert!(matches!(
+            backend.read_event().unwrap(),
+            Event::Key(KeyEvent { code: KeyCode::Char('a'), .. })
+        ));
+        backend.read_event().unwrap();
+        backend.read_event().unwrap();
+
+        let err = backend.read_event().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_memory_backend_clock_follows_events() {
+        let mut backend = MemoryBackend::new();
+        let start = backend.now();
+        backend.push_event_at(Duration::from_millis(1500), Event::FocusGained);
+        backend.push_key(KeyCode::Enter);
+
+        backend.read_event().unwrap();
+        assert_eq!(backend.now() - start, Duration::from_millis(1500));
+        backend.read_event().unwrap();
+        assert_eq!(backend.now() - start, Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn test_memory_backend_captures_output() {
+        let mut backend = MemoryBackend::with_size(20, 10);
+        backend.clear_screen().unwrap();
+        write_centered(&mut backend, "hi").unwrap();
+
+        assert!(backend.output().contains('\x1B'));
+        assert_eq!(backend.plain_output(), "         hi\r\n");
+    }
+
+    #[test]
+    fn test_center_text() {
+        assert_eq!(center_text("abc", 9), "   abc");
+        assert_eq!(center_text("too long", 4), "too long");
+    }
+}
@@ -5,7 +5,7 @@ use crossterm::{
     terminal::{Clear, ClearType},
     cursor,
 };
-use std::io::{stdout, Write};
+use std::io::stdout;
 
 /// Helper function to center text in terminal
 fn center_text(text: &str) -> String {
@@ -0,0 +1,604 @@
+//! Typing session state machine
+//!
+//! The keystroke handling shared by drills and speed tests, free of any
+//! terminal IO: a [`TypingSession`] is fed key events with the time they
+//! arrived and keeps track of what has been typed against the exercise
+//! text, which characters were right, and the running statistics. The
+//! exercises own everything else, from drawing the text to the Escape and
+//! function keys.
+
+use std::time::{Duration, Instant};
+use crossterm::event::{KeyCode, KeyEvent};
+use serde::{Deserialize, Serialize};
+use crate::performance::{ExerciseResult, PerformanceTracker};
+use crate::script::commands::{OptionSetting, TypingOption};
+
+/// Typing options in effect for an exercise, from the command line and O:
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TypingOptions {
+    /// Like a word processor (`-w`): a space or Enter stands for any run of
+    /// spaces with at most one line break, hyphens at line ends need not
+    /// be typed, and text wraps at the screen edge
+    pub word_processor: bool,
+    /// Sync-ahead, on unless `-a` is given as in C gtypist: a key matching
+    /// one of the next couple of target characters skips the ones left
+    /// out, and a key that repeats the one before the cursor is taken as
+    /// an extra one, so a slip is one error rather than a line of them
+    pub sync_ahead: bool,
+    /// Stop on error: a wrong key is counted and shown, but the cursor
+    /// stays put until the right one is pressed
+    pub stop_on_error: bool,
+    /// Show errors (`-i`): wrong characters are drawn in reverse video
+    pub show_errors: bool,
+}
+
+impl Default for TypingOptions {
+    fn default() -> Self {
+        Self {
+            word_processor: false,
+            sync_ahead: true,
+            stop_on_error: false,
+            show_errors: false,
+        }
+    }
+}
+
+impl TypingOptions {
+    /// Whether one option is on
+    pub fn is_set(&self, option: TypingOption) -> bool {
+        match option {
+            TypingOption::WordProcessor => self.word_processor,
+            TypingOption::SyncAhead => self.sync_ahead,
+            TypingOption::StopOnError => self.stop_on_error,
+            TypingOption::ShowErrors => self.show_errors,
+        }
+    }
+
+    /// Turn one option on or off
+    pub fn set(&mut self, option: TypingOption, enabled: bool) {
+        match option {
+            TypingOption::WordProcessor => self.word_processor = enabled,
+            TypingOption::SyncAhead => self.sync_ahead = enabled,
+            TypingOption::StopOnError => self.stop_on_error = enabled,
+            TypingOption::ShowErrors => self.show_errors = enabled,
+        }
+    }
+
+    /// These options with the settings of an O: command applied
+    pub fn with(mut self, settings: &[OptionSetting]) -> Self {
+        for setting in settings {
+            self.set(setting.option, setting.enabled);
+        }
+        self
+    }
+}
+
+/// Character produced by a key press, including Enter and Tab
+fn typed_char(key: &KeyEvent) -> Option<char> {
+    match key.code {
+        KeyCode::Char(ch) => Some(ch),
+        KeyCode::Enter => Some('\n'),
+        KeyCode::Tab => Some('\t'),
+        _ => None,
+    }
+}
+
+/// Target characters a typed key may jump ahead by under sync-ahead
+const SYNC_AHEAD: usize = 2;
+
+/// How one character of the exercise text has been typed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharStatus {
+    /// Not typed yet
+    Pending,
+    Correct,
+    /// Typed as the given wrong character
+    Wrong(char),
+    /// Left out, and skipped by sync-ahead
+    Missed,
+}
+
+/// What a key did to the session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEffect {
+    /// Not a typing key, or nothing left to type
+    Ignored,
+    /// A character was typed, rightly or not
+    Typed { correct: bool },
+    /// The last typed character was taken back
+    Erased,
+    /// Sync-ahead took the key as a slip and put the session back in step
+    Resynced,
+    /// A wrong key under stop-on-error: counted, but the cursor stays put
+    Rejected,
+}
+
+/// How a position of the text came to be filled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    /// By a key
+    Typed,
+    /// By word-processor folding
+    Filled,
+    /// Skipped by sync-ahead
+    Missed,
+}
+
+/// Whitespace a word processor treats as interchangeable
+fn is_foldable(ch: char) -> bool {
+    ch == ' ' || ch == '\n'
+}
+
+/// Progress through one exercise text
+#[derive(Debug, Clone)]
+pub struct TypingSession {
+    target: Vec<char>,
+    typed: Vec<char>,
+    /// How each position of `typed` was filled
+    slots: Vec<Slot>,
+    /// Wrong key held at the cursor under stop-on-error
+    rejected: Option<char>,
+    options: TypingOptions,
+    tracker: PerformanceTracker,
+    started: Instant,
+}
+
+impl TypingSession {
+    /// Start typing `text`, timed from `started`
+    pub fn new(text: &str, started: Instant) -> Self {
+        Self {
+            target: text.chars().collect(),
+            typed: Vec::new(),
+            slots: Vec::new(),
+            rejected: None,
+            options: TypingOptions::default(),
+            tracker: PerformanceTracker::new(),
+            started,
+        }
+    }
+
+    /// Type under the given options
+    pub fn with_options(mut self, options: TypingOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn options(&self) -> TypingOptions {
+        self.options
+    }
+
+    /// Apply a key pressed at time `at`
+    pub fn handle_key(&mut self, key: &KeyEvent, at: Instant) -> KeyEffect {
+        self.tracker.set_duration(self.elapsed(at));
+
+        if key.code == KeyCode::Backspace {
+            // A rejected key was never typed, so only it goes
+            if self.rejected.take().is_none() && !self.erase() {
+                return KeyEffect::Ignored;
+            }
+            self.tracker.record_backspace();
+            return KeyEffect::Erased;
+        }
+
+        let Some(ch) = typed_char(key) else { return KeyEffect::Ignored };
+        if self.options.word_processor && ch != '-' {
+            self.skip_line_end_hyphen();
+        }
+        if self.is_complete() {
+            return KeyEffect::Ignored;
+        }
+
+        let expected = self.target[self.typed.len()];
+        let folded = self.options.word_processor && is_foldable(ch) && is_foldable(expected);
+        let correct = ch == expected || folded;
+        if !correct && self.options.stop_on_error {
+            self.rejected = Some(ch);
+            self.tracker.record_error();
+            return KeyEffect::Rejected;
+        }
+        self.rejected = None;
+        if !correct && self.options.sync_ahead && self.sync_ahead(ch) {
+            return KeyEffect::Resynced;
+        }
+        // Keep the expected character when folding, so it reads as correct
+        self.push(if folded { expected } else { ch }, Slot::Typed);
+        if folded {
+            self.fold_whitespace(expected == '\n');
+        }
+
+        if correct {
+            self.tracker.record_correct_char();
+        } else {
+            self.tracker.record_error();
+        }
+        KeyEffect::Typed { correct }
+    }
+
+    fn push(&mut self, ch: char, slot: Slot) {
+        self.typed.push(ch);
+        self.slots.push(slot);
+    }
+
+    /// Take back the last typed character, with anything folding filled in
+    /// or sync-ahead skipped around it
+    fn erase(&mut self) -> bool {
+        self.drop_untyped();
+        if self.typed.pop().is_none() {
+            return false;
+        }
+        self.slots.pop();
+        self.drop_untyped();
+        true
+    }
+
+    fn drop_untyped(&mut self) {
+        while self.slots.last().is_some_and(|&slot| slot != Slot::Typed) {
+            self.slots.pop();
+            self.typed.pop();
+        }
+    }
+
+    /// Put the session back in step after a wrong key, if the key shows a
+    /// slip, and count the slip
+    ///
+    /// A key matching one of the next [`SYNC_AHEAD`] characters means the
+    /// ones before it were left out. A key matching the character before
+    /// the cursor was pressed twice if that one was typed right, or comes
+    /// after an extra key if it was typed wrong.
+    fn sync_ahead(&mut self, ch: char) -> bool {
+        let position = self.typed.len();
+        if let Some(skip) = (1..=SYNC_AHEAD).find(|&skip| self.target.get(position + skip) == Some(&ch)) {
+            for index in position..position + skip {
+                self.push(self.target[index], Slot::Missed);
+            }
+            self.push(ch, Slot::Typed);
+            self.tracker.record_slip();
+            self.tracker.record_correct_char();
+            return true;
+        }
+
+        let previous = position.checked_sub(1)
+            .filter(|&index| self.slots[index] == Slot::Typed && self.target[index] == ch);
+        match previous {
+            Some(index) if self.typed[index] == ch => self.tracker.record_slip(),
+            Some(index) => {
+                // The wrong key before was the extra one, and already an error
+                self.typed[index] = ch;
+                self.tracker.record_correct_char();
+            },
+            None => return false,
+        }
+        true
+    }
+
+    /// Fill in the rest of a whitespace run after a folded space or Enter,
+    /// up to and including at most one line break
+    fn fold_whitespace(&mut self, mut line_broken: bool) {
+        while let Some(&next) = self.target.get(self.typed.len()) {
+            match next {
+                ' ' => {},
+                '\n' if !line_broken => line_broken = true,
+                _ => break,
+            }
+            self.push(next, Slot::Filled);
+        }
+    }
+
+    /// Fill in a hyphen that splits a word across lines, and the line break
+    fn skip_line_end_hyphen(&mut self) {
+        let position = self.typed.len();
+        if self.target.get(position) == Some(&'-') && self.target.get(position + 1) == Some(&'\n') {
+            self.push('-', Slot::Filled);
+            self.push('\n', Slot::Filled);
+            self.fold_whitespace(true);
+        }
+    }
+
+    /// The exercise text
+    pub fn target(&self) -> &[char] {
+        &self.target
+    }
+
+    /// What has been typed so far, one character per target character;
+    /// missed characters hold the target character, so go by [`status`]
+    ///
+    /// [`status`]: TypingSession::status
+    pub fn typed(&self) -> &[char] {
+        &self.typed
+    }
+
+    /// Wrong key last pressed at the cursor, under stop-on-error
+    pub fn rejected(&self) -> Option<char> {
+        self.rejected
+    }
+
+    /// Index of the next character to type
+    pub fn position(&self) -> usize {
+        self.typed.len()
+    }
+
+    /// How the character at `index` of the text has been typed
+    pub fn status(&self, index: usize) -> CharStatus {
+        match self.typed.get(index) {
+            None => CharStatus::Pending,
+            Some(_) if self.slots[index] == Slot::Missed => CharStatus::Missed,
+            Some(&ch) if ch == self.target[index] => CharStatus::Correct,
+            Some(&ch) => CharStatus::Wrong(ch),
+        }
+    }
+
+    /// True once every character of the text has been typed
+    pub fn is_complete(&self) -> bool {
+        self.typed.len() >= self.target.len()
+    }
+
+    /// Statistics as of the last key
+    pub fn tracker(&self) -> &PerformanceTracker {
+        &self.tracker
+    }
+
+    /// Time since the session started
+    pub fn elapsed(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.started)
+    }
+
+    /// Stop the clock at `at` and sum up what was typed
+    pub fn finish(&mut self, at: Instant) -> ExerciseResult {
+        let duration = self.elapsed(at);
+        self.tracker.set_duration(duration);
+        ExerciseResult {
+            total_chars: self.position(),
+            correct_chars: self.tracker.correct_chars(),
+            errors: self.tracker.errors(),
+            duration,
+            wpm: self.tracker.words_per_minute(),
+            error_rate: self.tracker.error_rate(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+    use proptest::prelude::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    /// Every option off, sync-ahead included, so keys are compared one
+    /// for one
+    fn strict() -> TypingOptions {
+        TypingOptions { sync_ahead: false, ..TypingOptions::default() }
+    }
+
+    fn type_text(session: &mut TypingSession, text: &str, start: Instant) {
+        for (i, ch) in text.chars().enumerate() {
+            let code = match ch {
+                '\n' => KeyCode::Enter,
+                '\t' => KeyCode::Tab,
+                ch => KeyCode::Char(ch),
+            };
+            session.handle_key(&key(code), start + Duration::from_millis(100 * (i as u64 + 1)));
+        }
+    }
+
+    #[test]
+    fn test_typing_and_backspace() {
+        let start = Instant::now();
+        let mut session = TypingSession::new("ab\nc", start);
+
+        assert_eq!(session.handle_key(&key(KeyCode::Char('a')), start), KeyEffect::Typed { correct: true });
+        assert_eq!(session.handle_key(&key(KeyCode::Char('x')), start), KeyEffect::Typed { correct: false });
+        assert_eq!(session.status(1), CharStatus::Wrong('x'));
+        assert_eq!(session.handle_key(&key(KeyCode::Backspace), start), KeyEffect::Erased);
+        assert_eq!(session.status(1), CharStatus::Pending);
+        assert_eq!(session.handle_key(&key(KeyCode::F(3)), start), KeyEffect::Ignored);
+
+        type_text(&mut session, "b\nc", start);
+        assert!(session.is_complete());
+        assert_eq!(session.handle_key(&key(KeyCode::Char('z')), start), KeyEffect::Ignored);
+
+        let result = session.finish(start + Duration::from_secs(6));
+        assert_eq!(result.total_chars, 4);
+        assert_eq!(result.correct_chars, 4);
+        assert_eq!(result.errors, 1);
+        assert_eq!(result.duration, Duration::from_secs(6));
+        assert_eq!(session.tracker().backspaces(), 1);
+    }
+
+    fn any_key() -> impl Strategy<Value = KeyEvent> {
+        prop_oneof![
+            4 => proptest::char::range('a', 'e').prop_map(|ch| key(KeyCode::Char(ch))),
+            1 => Just(key(KeyCode::Enter)),
+            1 => Just(key(KeyCode::Backspace)),
+            1 => Just(key(KeyCode::Esc)),
+        ]
+    }
+
+    #[test]
+    fn test_word_processor_folding() {
+        let start = Instant::now();
+        let options = TypingOptions { word_processor: true, ..TypingOptions::default() };
+        let mut session = TypingSession::new("a  b\n  c-\nd\n\ne", start).with_options(options);
+
+        type_text(&mut session, "a b c", start);
+        assert_eq!(session.position(), 8);
+        assert_eq!(session.status(7), CharStatus::Correct);
+
+        // The hyphen and line break at the end of the line are skipped
+        type_text(&mut session, "d", start);
+        assert_eq!(session.position(), 11);
+
+        // Backspace takes back the typed letter and what was skipped for it
+        assert_eq!(session.handle_key(&key(KeyCode::Backspace), start), KeyEffect::Erased);
+        assert_eq!(session.position(), 8);
+        type_text(&mut session, "-\nd", start);
+
+        // A blank line is a paragraph break, so needs a second Enter
+        type_text(&mut session, " ", start);
+        assert_eq!(session.position(), 12);
+        type_text(&mut session, "\ne", start);
+        assert!(session.is_complete());
+        assert_eq!(session.tracker().errors(), 0);
+
+        // Without the option, whitespace is compared exactly
+        let mut session = TypingSession::new("a  b", start).with_options(strict());
+        type_text(&mut session, "a b", start);
+        assert_eq!(session.status(2), CharStatus::Wrong('b'));
+    }
+
+    #[test]
+    fn test_sync_ahead_recovers_from_slips() {
+        let start = Instant::now();
+        let options = TypingOptions { sync_ahead: true, ..TypingOptions::default() };
+
+        // A dropped letter is skipped, and costs one error
+        let mut session = TypingSession::new("the cat", start).with_options(options);
+        assert_eq!(session.handle_key(&key(KeyCode::Char('t')), start), KeyEffect::Typed { correct: true });
+        assert_eq!(session.handle_key(&key(KeyCode::Char('e')), start), KeyEffect::Resynced);
+        assert_eq!(session.status(1), CharStatus::Missed);
+        assert_eq!(session.status(2), CharStatus::Correct);
+
+        // So are two
+        type_text(&mut session, " t", start);
+        assert_eq!(session.position(), 7);
+        assert_eq!(session.status(5), CharStatus::Missed);
+        assert_eq!(session.status(6), CharStatus::Correct);
+        assert!(session.is_complete());
+        let tracker = session.tracker();
+        assert_eq!((tracker.errors(), tracker.slips(), tracker.correct_chars()), (2, 2, 4));
+
+        // A doubled key doesn't move the cursor
+        let mut session = TypingSession::new("abc", start).with_options(options);
+        type_text(&mut session, "abbc", start);
+        assert!(session.is_complete());
+        assert_eq!(session.tracker().errors(), 1);
+
+        // A stray key counts as it is typed, and the right key after it
+        // takes its place
+        let mut session = TypingSession::new("abcd", start).with_options(options);
+        type_text(&mut session, "axbcd", start);
+        assert!(session.is_complete());
+        assert!((0..4).all(|i| session.status(i) == CharStatus::Correct));
+        assert_eq!(session.tracker().errors(), 1);
+
+        // Backspace after a skip takes back the skipped letters too
+        let mut session = TypingSession::new("abc", start).with_options(options);
+        type_text(&mut session, "c", start);
+        assert_eq!(session.position(), 3);
+        assert_eq!(session.handle_key(&key(KeyCode::Backspace), start), KeyEffect::Erased);
+        assert_eq!(session.position(), 0);
+
+        // Without the option, everything after the dropped letter is wrong
+        let mut session = TypingSession::new("the cat", start).with_options(strict());
+        type_text(&mut session, "te cat", start);
+        assert_eq!(session.tracker().errors(), 5);
+    }
+
+    #[test]
+    fn test_stop_on_error_holds_the_cursor() {
+        let start = Instant::now();
+        let options = TypingOptions { stop_on_error: true, sync_ahead: true, ..TypingOptions::default() };
+        let mut session = TypingSession::new("abc", start).with_options(options);
+
+        // Stop-on-error wins over sync-ahead, which would skip the b
+        type_text(&mut session, "a", start);
+        assert_eq!(session.handle_key(&key(KeyCode::Char('c')), start), KeyEffect::Rejected);
+        assert_eq!(session.handle_key(&key(KeyCode::Char('x')), start), KeyEffect::Rejected);
+        assert_eq!(session.position(), 1);
+        assert_eq!(session.rejected(), Some('x'));
+
+        // Backspace only clears the wrong key
+        assert_eq!(session.handle_key(&key(KeyCode::Backspace), start), KeyEffect::Erased);
+        assert_eq!((session.position(), session.rejected()), (1, None));
+
+        type_text(&mut session, "bc", start);
+        assert!(session.is_complete());
+        assert!((0..3).all(|i| session.status(i) == CharStatus::Correct));
+        let result = session.finish(start);
+        assert_eq!((result.correct_chars, result.errors), (3, 2));
+    }
+
+    proptest! {
+        #[test]
+        fn prop_exact_typing_completes_without_errors(text in "[a-e \n\t]{1,40}") {
+            let start = Instant::now();
+            let mut session = TypingSession::new(&text, start);
+            type_text(&mut session, &text, start);
+
+            prop_assert!(session.is_complete());
+            let result = session.finish(start + Duration::from_secs(1));
+            prop_assert_eq!(result.errors, 0);
+            prop_assert_eq!(result.correct_chars, text.chars().count());
+            prop_assert!((0..text.chars().count()).all(|i| session.status(i) == CharStatus::Correct));
+        }
+
+        #[test]
+        fn prop_statistics_follow_keys(text in "[a-e\n]{1,20}", keys in proptest::collection::vec(any_key(), 0..60)) {
+            let start = Instant::now();
+            let mut session = TypingSession::new(&text, start).with_options(strict());
+            let (mut typed, mut erased) = (0, 0);
+
+            for key in &keys {
+                let before = session.position();
+                match session.handle_key(key, start) {
+                    KeyEffect::Typed { correct } => {
+                        typed += 1;
+                        prop_assert_eq!(session.position(), before + 1);
+                        prop_assert_eq!(session.status(before) == CharStatus::Correct, correct);
+                    },
+                    KeyEffect::Erased => {
+                        erased += 1;
+                        prop_assert_eq!(session.position(), before - 1);
+                    },
+                    KeyEffect::Ignored => prop_assert_eq!(session.position(), before),
+                    KeyEffect::Resynced | KeyEffect::Rejected => prop_assert!(false, "no typing options are on"),
+                }
+                prop_assert!(session.position() <= session.target().len());
+            }
+
+            let tracker = session.tracker();
+            prop_assert_eq!(tracker.correct_chars() + tracker.errors(), typed);
+            prop_assert_eq!(tracker.backspaces(), erased);
+            prop_assert_eq!(session.position(), typed - erased);
+            prop_assert!((session.position()..text.chars().count()).all(|i| session.status(i) == CharStatus::Pending));
+        }
+
+        #[test]
+        fn prop_sync_ahead_dropped_char_is_one_error(text in "[a-c ]{2,30}", drop in any::<proptest::sample::Index>()) {
+            let mut chars: Vec<char> = text.chars().collect();
+            let length = chars.len();
+            chars.remove(drop.index(length));
+            let start = Instant::now();
+            let options = TypingOptions { sync_ahead: true, ..TypingOptions::default() };
+            let mut session = TypingSession::new(&text, start).with_options(options);
+            type_text(&mut session, &chars.into_iter().collect::<String>(), start);
+
+            // Only a drop at the very end leaves the text one short
+            prop_assert!(session.position() >= length - 1);
+            prop_assert!(session.tracker().errors() <= 1);
+        }
+
+        #[test]
+        fn prop_word_processor_accepts_single_spaces(
+            words in proptest::collection::vec("[a-e]{1,6}", 1..10),
+            gaps in proptest::collection::vec("( {1,3}| *\n *)", 9),
+        ) {
+            let mut text = words[0].clone();
+            for (word, gap) in words[1..].iter().zip(&gaps) {
+                text.push_str(gap);
+                text.push_str(word);
+            }
+            let start = Instant::now();
+            let mut session = TypingSession::new(&text, start).with_options(TypingOptions { word_processor: true, ..TypingOptions::default() });
+            type_text(&mut session, &words.join(" "), start);
+
+            prop_assert!(session.is_complete());
+            prop_assert_eq!(session.tracker().errors(), 0);
+            prop_assert!((0..text.chars().count()).all(|i| session.status(i) == CharStatus::Correct));
+        }
+    }
+}
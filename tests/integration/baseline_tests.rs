@@ -152,15 +152,15 @@ E:10*
     // Verify error rate commands are parsed correctly
     let error_commands: Vec<_> = script.commands.iter()
         .filter_map(|cmd| match cmd {
-            Command::ErrorMaxSet { percentage } => Some(*percentage),
+            Command::ErrorMaxSet { percentage, .. } => Some(*percentage),
             _ => None,
         })
         .collect();
     
     assert_eq!(error_commands.len(), 3);
-    assert!(error_commands.contains(&5.0));
-    assert!(error_commands.contains(&0.0)); // "default" maps to 0.0
-    assert!(error_commands.contains(&10.0));
+    assert!(error_commands.contains(&Some(5.0)));
+    assert!(error_commands.contains(&None)); // "default"
+    assert!(error_commands.contains(&Some(10.0)));
 }
 
 #[test]
@@ -1,6 +1,6 @@
 //! Integration tests for GNU Typist Rust implementation
 
-use gtypist_rs::{Script, Executor};
+use gtypist_rs::{Script, Executor, ExecutionResult, MemoryBackend};
 use std::io::Write;
 use tempfile::NamedTempFile;
 
@@ -69,7 +69,7 @@ X:
     let script = Script::from_file(temp_file.path().to_str().unwrap()).unwrap();
     let executor = Executor::new(script);
     
-    assert_eq!(executor.error_percentage, 0.0);
+    assert_eq!(executor.error_percentage, gtypist_rs::script::executor::DEFAULT_MAX_ERROR);
     assert!(executor.failure_label.is_none());
 }
 
@@ -90,7 +90,7 @@ M: "Test Menu"
     assert_eq!(script.commands.len(), 1);
     
     match &script.commands[0] {
-        gtypist_rs::script::commands::Command::Menu { title, items } => {
+        gtypist_rs::script::commands::Command::Menu { title, items, .. } => {
             assert_eq!(title, "Test Menu");
             assert_eq!(items.len(), 2);
             assert_eq!(items[0].label, "ITEM1");
@@ -106,24 +106,49 @@ fn test_error_percentage_parsing() {
     
     // Test percentage with % sign
     let cmd = Command::parse_line("E:5%", 1).unwrap().unwrap();
-    match cmd {
-        Command::ErrorMaxSet { percentage } => assert_eq!(percentage, 5.0),
-        _ => panic!("Expected ErrorMaxSet command"),
-    }
+    assert_eq!(cmd, Command::ErrorMaxSet { percentage: Some(5.0), persistent: false });
     
     // Test percentage with %* suffix
-    let cmd = Command::parse_line("E:10%*", 1).unwrap().unwrap();
-    match cmd {
-        Command::ErrorMaxSet { percentage } => assert_eq!(percentage, 10.0),
-        _ => panic!("Expected ErrorMaxSet command"),
-    }
+    let cmd = Command::parse_line("E: 10.0%*", 1).unwrap().unwrap();
+    assert_eq!(cmd, Command::ErrorMaxSet { percentage: Some(10.0), persistent: true });
     
     // Test default keyword
-    let cmd = Command::parse_line("E:default", 1).unwrap().unwrap();
-    match cmd {
-        Command::ErrorMaxSet { percentage } => assert_eq!(percentage, 0.0),
-        _ => panic!("Expected ErrorMaxSet command"),
-    }
+    let cmd = Command::parse_line("E:Default", 1).unwrap().unwrap();
+    assert_eq!(cmd, Command::ErrorMaxSet { percentage: None, persistent: false });
+    
+    // F: takes the same marker, and NULL clears the label
+    let cmd = Command::parse_line("F:RETRY*", 1).unwrap().unwrap();
+    assert_eq!(cmd, Command::OnFailureSet { label: Some("RETRY".to_string()), persistent: true });
+    let cmd = Command::parse_line("F:NULL", 1).unwrap().unwrap();
+    assert_eq!(cmd, Command::OnFailureSet { label: None, persistent: false });
+    
+    // So does O:, with no- turning an option off
+    use gtypist_rs::script::commands::{OptionSetting, TypingOption};
+    let cmd = Command::parse_line("O:no-word-processor*", 1).unwrap().unwrap();
+    let setting = OptionSetting { option: TypingOption::WordProcessor, enabled: false };
+    assert_eq!(cmd, Command::OptionSet { options: vec![setting], persistent: true });
+    assert!(Command::parse_line("O:typewriter", 1).is_err());
+    assert!(Command::parse_line("O:", 1).is_err());
+}
+
+#[test]
+fn test_lesson_metadata_header() {
+    use gtypist_rs::LessonMetadata;
+    
+    let script_content = "# Comment before\n#@title: Home row\n#@layout: us\n#@entry: START\nT:Intro\n*:START\nX:\n";
+    let mut temp_file = NamedTempFile::new().unwrap();
+    temp_file.write_all(script_content.as_bytes()).unwrap();
+    
+    let script = Script::from_file(temp_file.path().to_str().unwrap()).unwrap();
+    
+    assert_eq!(script.metadata, LessonMetadata {
+        title: Some("Home row".to_string()),
+        layout: Some("us".to_string()),
+        entry: Some("START".to_string()),
+        ..LessonMetadata::default()
+    });
+    // The header is still a set of comments to the executor
+    assert_eq!(script.commands.len(), 7);
 }
 
 #[test]
@@ -185,4 +210,569 @@ T:End reached
         },
         _ => panic!("Expected to be at END label"),
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_headless_execution() {
+    use crossterm::event::KeyCode;
+    
+    let script_content = r#"
+*:START
+B:Headless
+T:Tutorial text
+Q:Continue?
+N:END
+M: "Menu"
+ :ONE  "First"
+ :END  "Finish"
+*:ONE
+X:
+*:END
+X:
+"#;
+    
+    let mut temp_file = NamedTempFile::new().unwrap();
+    temp_file.write_all(script_content.as_bytes()).unwrap();
+    
+    let script = Script::from_file(temp_file.path().to_str().unwrap()).unwrap();
+    let mut backend = MemoryBackend::new();
+    backend.push_key(KeyCode::Char(' ')); // dismiss tutorial
+    backend.push_key(KeyCode::Char('y')); // answer query
+    backend.push_key(KeyCode::Char('2')); // pick "Finish"
+    let mut executor = Executor::with_backend(script, Box::new(backend));
+    
+    loop {
+        match executor.execute_next().unwrap() {
+            ExecutionResult::Exit | ExecutionResult::Finished => break,
+            _ => continue,
+        }
+    }
+    
+    assert_eq!(executor.last_query_response, Some(true));
+    assert_eq!(executor.script.position, executor.script.labels["END"] + 1);
+}
+
+#[test]
+fn test_headless_runner_follows_branches() {
+    use gtypist_rs::runner::{self, RunOutcome};
+    
+    let script_content = r#"
+*:START
+Q:Take the drill?
+N:SKIP
+*:DRILL
+D:abc
+*:SKIP
+X:
+"#;
+    
+    let mut temp_file = NamedTempFile::new().unwrap();
+    temp_file.write_all(script_content.as_bytes()).unwrap();
+    let script = Script::from_file(temp_file.path().to_str().unwrap()).unwrap();
+    
+    let events = runner::parse_key_events("0 y\n1000 a\n1500 b\n2000 c\n2500 <Enter>").unwrap();
+    let mut executor = Executor::with_backend(script, Box::new(runner::backend_from_events(events)));
+    let report = runner::run(&mut executor).unwrap();
+    
+    assert_eq!(report.labels, vec!["START", "DRILL", "SKIP"]);
+    assert_eq!(report.outcome, RunOutcome::Exit);
+    assert_eq!(report.results.len(), 1);
+    assert_eq!(report.results[0].correct_chars, 3);
+    assert_eq!(report.results[0].duration.as_millis(), 2000);
+}
+
+#[test]
+fn test_key_binding_jumps_from_exercise() {
+    use crossterm::event::KeyCode;
+    
+    let script_content = r#"
+K:12:MENU
+K:3:NULL
+D:a long drill
+X:
+*:MENU
+X:
+"#;
+    
+    let mut temp_file = NamedTempFile::new().unwrap();
+    temp_file.write_all(script_content.as_bytes()).unwrap();
+    let script = Script::from_file(temp_file.path().to_str().unwrap()).unwrap();
+    
+    let mut backend = MemoryBackend::new();
+    backend.push_text("a ");
+    backend.push_key(KeyCode::F(3)); // unbound, ignored
+    backend.push_key(KeyCode::F(12));
+    let mut executor = Executor::with_backend(script, Box::new(backend));
+    
+    while !matches!(executor.execute_next().unwrap(), ExecutionResult::Exit) {}
+    
+    assert_eq!(executor.key_bindings.get(&12).map(String::as_str), Some("MENU"));
+    assert_eq!(executor.script.position, executor.script.labels["MENU"] + 1);
+    assert!(executor.results.is_empty());
+}
+
+#[test]
+fn test_missing_label_reports_jump_location() {
+    let script_content = "*:START\nT:Intro\nG:NOWHERE\n";
+    
+    let mut temp_file = NamedTempFile::new().unwrap();
+    temp_file.write_all(script_content.as_bytes()).unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    let script = Script::from_file(path).unwrap();
+    
+    let mut backend = MemoryBackend::new();
+    backend.push_text(" ");
+    let mut executor = Executor::with_backend(script, Box::new(backend));
+    
+    let error = loop {
+        if let Err(e) = executor.execute_next() {
+            break e;
+        }
+    };
+    
+    match &error {
+        gtypist_rs::ScriptError::LabelNotFound { label, span: Some(span) } => {
+            assert_eq!(label, "NOWHERE");
+            assert_eq!((span.line, span.column), (3, 1));
+        },
+        other => panic!("unexpected error {:?}", other),
+    }
+    assert_eq!(error.to_string(), format!("Label not found: NOWHERE (referenced at {}:3:1)", path));
+}
+
+#[test]
+fn test_included_file_commands_resolve_relative_to_include() {
+    use gtypist_rs::runner;
+    
+    let dir = tempfile::TempDir::new().unwrap();
+    std::fs::create_dir(dir.path().join("shared")).unwrap();
+    std::fs::write(dir.path().join("main.typ"), "@:shared/drills.typ:SHARED\nX:\n").unwrap();
+    std::fs::write(dir.path().join("shared/drills.typ"), "*:DRILL\nf:drill.txt\n").unwrap();
+    std::fs::write(dir.path().join("shared/drill.txt"), "ab").unwrap();
+    
+    let script = Script::from_file(dir.path().join("main.typ").to_str().unwrap()).unwrap();
+    assert!(script.labels.contains_key("SHARED.DRILL"));
+    
+    let events = runner::parse_key_events("0 a\n500 b\n600 <Enter>").unwrap();
+    let mut executor = Executor::with_backend(script, Box::new(runner::backend_from_events(events)));
+    let report = runner::run(&mut executor).unwrap();
+    
+    assert_eq!(report.labels, vec!["SHARED.DRILL"]);
+    assert_eq!(report.results.len(), 1);
+    assert_eq!(report.results[0].correct_chars, 2);
+}
+
+#[test]
+fn test_performance_branching() {
+    use gtypist_rs::runner;
+    
+    let script_content = r#"
+J:errors > 0:NEVER
+E:100%
+D:abcd
+J:errors >= 2:REMEDIAL
+*:FAST
+X:
+*:REMEDIAL
+J:wpm < 30:SLOW
+X:
+*:SLOW
+X:
+*:NEVER
+X:
+"#;
+    
+    let mut temp_file = NamedTempFile::new().unwrap();
+    temp_file.write_all(script_content.as_bytes()).unwrap();
+    let script = Script::from_file(temp_file.path().to_str().unwrap()).unwrap();
+    
+    // Two mistakes typed slowly
+    let events = runner::parse_key_events("0 a\n10000 x\n20000 y\n30000 d\n30100 <Enter>").unwrap();
+    let mut executor = Executor::with_backend(script, Box::new(runner::backend_from_events(events)));
+    let report = runner::run(&mut executor).unwrap();
+    
+    assert_eq!(report.labels, vec!["REMEDIAL", "SLOW"]);
+    assert_eq!(executor.last_result().unwrap().errors, 2);
+}
+
+/// Key file typing each drill text with its first character wrong,
+/// then dismissing the results screen
+///
+/// An entry like `<n>` or `<Enter>` is pressed as that single key instead,
+/// e.g. to answer the prompt after a failed drill.
+fn one_error_keys(texts: &[&str]) -> String {
+    let mut keys = String::new();
+    let mut time = 0;
+    for text in texts {
+        if let Some(key) = text.strip_prefix('<').and_then(|key| key.strip_suffix('>')) {
+            let key = if key.chars().count() == 1 { key } else { text };
+            keys.push_str(&format!("{} {}\n", time, key));
+            time += 1000;
+            continue;
+        }
+        let typed = std::iter::once("x".to_string()).chain(text.chars().skip(1).map(String::from));
+        for key in typed.chain(std::iter::once("<Enter>".to_string())) {
+            keys.push_str(&format!("{} {}\n", time, key));
+            time += 1000;
+        }
+    }
+    keys
+}
+
+#[test]
+fn test_one_shot_and_persistent_error_settings() {
+    use gtypist_rs::runner;
+    
+    // Every drill is typed at 25% errors
+    let script_content = r#"
+E:30%
+F:MISSED
+D:abcd
+D:abcd
+E:50%*
+F:RETRY*
+D:abcd
+E:default
+D:abcd
+X:
+*:RETRY
+F:NULL
+E:default*
+D:abcd
+X:
+*:MISSED
+X:
+"#;
+    
+    let mut temp_file = NamedTempFile::new().unwrap();
+    temp_file.write_all(script_content.as_bytes()).unwrap();
+    let script = Script::from_file(temp_file.path().to_str().unwrap()).unwrap();
+    
+    let keys = one_error_keys(&["abcd", "abcd", "<n>", "abcd", "abcd", "abcd", "<n>"]);
+    let events = runner::parse_key_events(&keys).unwrap();
+    let mut executor = Executor::with_backend(script, Box::new(runner::backend_from_events(events)));
+    let report = runner::run(&mut executor).unwrap();
+    
+    // The one-shot E:30% and F:MISSED only covered the first drill, so the
+    // second fails to the prompt; E:default then fails against the
+    // persistent F:, and the last drill, after F:NULL, to the prompt again.
+    // Failed attempts are recorded along with the passes
+    assert_eq!(report.labels, vec!["RETRY"]);
+    assert_eq!(report.results.len(), 5);
+    assert_eq!(report.outcome, runner::RunOutcome::Exit);
+}
+
+#[test]
+fn test_word_processor_option_set_by_script() {
+    use gtypist_rs::runner;
+    
+    // The one-shot O: lets the first drill fold its double space; the
+    // second drill is typed space for space
+    let script_content = "O:word-processor\nD:a  b\nD:a  b\nX:\n";
+    let mut temp_file = NamedTempFile::new().unwrap();
+    temp_file.write_all(script_content.as_bytes()).unwrap();
+    let script = Script::from_file(temp_file.path().to_str().unwrap()).unwrap();
+    
+    let keys = "0 a\n100 <Space>\n200 b\n300 <Enter>\n400 a\n500 <Space>\n600 <Space>\n700 b\n800 <Enter>\n";
+    let events = runner::parse_key_events(keys).unwrap();
+    let mut executor = Executor::with_backend(script, Box::new(runner::backend_from_events(events)));
+    let report = runner::run(&mut executor).unwrap();
+    
+    assert_eq!(report.outcome, runner::RunOutcome::Exit);
+    assert_eq!(report.results.len(), 2);
+    assert!(report.results.iter().all(|result| result.errors == 0));
+    assert!(executor.next_typing_options.is_none());
+}
+
+#[test]
+fn test_repeated_drill_keeps_one_shot_settings() {
+    use gtypist_rs::runner;
+    
+    fn run(script_content: &str, keys: &[&str]) -> runner::RunReport {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(script_content.as_bytes()).unwrap();
+        let script = Script::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        let events = runner::parse_key_events(&one_error_keys(keys)).unwrap();
+        let mut executor = Executor::with_backend(script, Box::new(runner::backend_from_events(events)));
+        runner::run(&mut executor).unwrap()
+    }
+    
+    // One error in five keys passes only at 30%, and only with the double
+    // space folded
+    let passing = ["<x>", "<b>", "<Space>", "<c>", "<d>", "<Enter>"];
+    let failing = ["<x>", "<x>", "<x>", "<x>", "<x>", "<x>", "<Enter>"];
+    
+    // Repeated with R from the prompt after failing
+    let keys: Vec<&str> = failing.iter().chain(&["<r>"]).chain(&passing).copied().collect();
+    let report = run("E:30%\nO:word-processor\nD:ab  cd\nX:\n", &keys);
+    assert_eq!(report.outcome, runner::RunOutcome::Exit);
+    assert_eq!(report.results.len(), 2);
+    assert_eq!(report.results[1].errors, 1);
+    
+    // Restarted with Ctrl+R, then passed or failed to the one-shot F:
+    let script_content = "E:30%\nF:MISSED\nO:word-processor\nD:ab  cd\nX:\n*:MISSED\nX:\n";
+    let keys: Vec<&str> = ["<a>", "<C-r>"].iter().chain(&passing).copied().collect();
+    let report = run(script_content, &keys);
+    assert_eq!(report.outcome, runner::RunOutcome::Exit);
+    assert!(report.labels.is_empty());
+    assert_eq!(report.results.len(), 1);
+    
+    let keys: Vec<&str> = ["<a>", "<C-r>"].iter().chain(&failing).copied().collect();
+    let report = run(script_content, &keys);
+    assert_eq!(report.outcome, runner::RunOutcome::Exit);
+    assert_eq!(report.labels, vec!["MISSED"]);
+}
+
+#[test]
+fn test_one_shot_null_failure_label_keeps_persistent_one() {
+    use gtypist_rs::runner;
+    
+    let script_content = "F:AGAIN*\nF:NULL\nD:abcd\nD:abcd\nX:\n*:AGAIN\nX:\n";
+    let mut temp_file = NamedTempFile::new().unwrap();
+    temp_file.write_all(script_content.as_bytes()).unwrap();
+    let script = Script::from_file(temp_file.path().to_str().unwrap()).unwrap();
+    
+    let events = runner::parse_key_events(&one_error_keys(&["abcd", "<n>", "abcd"])).unwrap();
+    let mut executor = Executor::with_backend(script, Box::new(runner::backend_from_events(events)));
+    let report = runner::run(&mut executor).unwrap();
+    
+    // The one-shot F:NULL sends the first failure to the prompt; the
+    // second failure goes to the persistent label again
+    assert_eq!(report.labels, vec!["AGAIN"]);
+    assert_eq!(report.results.len(), 2);
+    assert_eq!(report.outcome, runner::RunOutcome::Exit);
+}
+
+#[test]
+fn test_performance_jump_judges_failed_drill() {
+    use gtypist_rs::runner;
+    
+    let script_content = "D:abcd\nE:0%\nD:ab\nJ:errors > 0:BAD\nX:\n*:BAD\nX:\n";
+    let mut temp_file = NamedTempFile::new().unwrap();
+    temp_file.write_all(script_content.as_bytes()).unwrap();
+    let script = Script::from_file(temp_file.path().to_str().unwrap()).unwrap();
+    
+    // Pass the first drill, fail the second and go on from the prompt
+    let keys = "0 a\n100 b\n200 c\n300 d\n400 <Enter>\n500 x\n600 b\n700 <Enter>\n800 n\n";
+    let events = runner::parse_key_events(keys).unwrap();
+    let mut executor = Executor::with_backend(script, Box::new(runner::backend_from_events(events)));
+    let report = runner::run(&mut executor).unwrap();
+    
+    // J: looks at the failed drill, not the one passed before it
+    assert_eq!(report.labels, vec!["BAD"]);
+    assert_eq!(report.results.len(), 2);
+    assert_eq!(report.results[1].errors, 1);
+}
+
+#[test]
+fn test_max_error_caps_script_thresholds() {
+    use gtypist_rs::runner;
+    
+    let script_content = "F:CAPPED*\nE:50%*\nD:abcd\nX:\n*:CAPPED\nE:default\nD:abcdefgh\nX:\n";
+    let mut temp_file = NamedTempFile::new().unwrap();
+    temp_file.write_all(script_content.as_bytes()).unwrap();
+    let script = Script::from_file(temp_file.path().to_str().unwrap()).unwrap();
+    
+    let events = runner::parse_key_events(&one_error_keys(&["abcd", "abcdefgh"])).unwrap();
+    let mut executor = Executor::with_backend(script, Box::new(runner::backend_from_events(events)));
+    executor.set_max_error(20.0);
+    let report = runner::run(&mut executor).unwrap();
+    
+    // E:50% is held to 20%, which the first drill fails at 25%; E:default
+    // goes back to the --max-error value, which the second passes at 12.5%
+    assert_eq!(report.labels, vec!["CAPPED"]);
+    assert_eq!(report.results.len(), 2);
+    assert_eq!(report.results[1].errors, 1);
+}
+
+#[test]
+fn test_menu_escape_walks_up() {
+    use gtypist_rs::runner;
+    
+    let script_content = r#"
+*:TOP
+M: "Top"
+ :SUB  "Submenu"
+ :END  "Quit"
+*:SUB
+M: "Sub"
+ :LEAF  "Leaf menu"
+*:LEAF
+M: UP=SUB "Leaf"
+ :END  "Quit"
+*:END
+X:
+"#;
+    
+    let mut temp_file = NamedTempFile::new().unwrap();
+    temp_file.write_all(script_content.as_bytes()).unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    
+    // Down into the leaf, Escape via UP=SUB, Escape back to the top menu by
+    // history, then pick its second item
+    let keys = "0 <Enter>\n100 <Enter>\n200 <Esc>\n300 <Esc>\n400 <Down>\n500 <Enter>\n";
+    let events = runner::parse_key_events(keys).unwrap();
+    let mut executor = Executor::with_backend(Script::from_file(path).unwrap(), Box::new(runner::backend_from_events(events)));
+    let report = runner::run(&mut executor).unwrap();
+    
+    assert_eq!(report.labels, vec!["TOP", "SUB", "LEAF", "SUB", "END"]);
+    assert_eq!(report.outcome, runner::RunOutcome::Exit);
+    
+    // Escape from the top menu quits
+    let events = runner::parse_key_events("0 <Esc>\n").unwrap();
+    let mut executor = Executor::with_backend(Script::from_file(path).unwrap(), Box::new(runner::backend_from_events(events)));
+    let report = runner::run(&mut executor).unwrap();
+    
+    assert_eq!(report.labels, vec!["TOP"]);
+    assert_eq!(report.outcome, runner::RunOutcome::Exit);
+    assert!(executor.menu_stack.is_empty());
+}
+
+#[test]
+fn test_subroutine_call_and_return() {
+    use gtypist_rs::runner;
+    
+    let script_content = r#"
+*:START
+C:ADVICE
+C:ADVICE
+X:
+*:ADVICE
+C:INNER
+R:
+*:INNER
+R:
+"#;
+    
+    let mut temp_file = NamedTempFile::new().unwrap();
+    temp_file.write_all(script_content.as_bytes()).unwrap();
+    let script = Script::from_file(temp_file.path().to_str().unwrap()).unwrap();
+    
+    let mut executor = Executor::with_backend(script, Box::new(MemoryBackend::new()));
+    let report = runner::run(&mut executor).unwrap();
+    
+    assert_eq!(report.labels, vec!["START", "ADVICE", "INNER", "ADVICE", "INNER"]);
+    assert_eq!(report.outcome, runner::RunOutcome::Exit);
+    assert!(executor.call_stack.is_empty());
+}
+
+#[test]
+fn test_subroutine_errors() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    temp_file.write_all(b"T:start\nR:\n").unwrap();
+    let script = Script::from_file(temp_file.path().to_str().unwrap()).unwrap();
+    let mut backend = MemoryBackend::new();
+    backend.push_text(" ");
+    let mut executor = Executor::with_backend(script, Box::new(backend));
+    
+    executor.execute_next().unwrap();
+    let error = executor.execute_next().unwrap_err();
+    assert!(error.to_string().ends_with(":2:1: R: without a matching C: call"), "{}", error);
+    
+    let mut temp_file = NamedTempFile::new().unwrap();
+    temp_file.write_all(b"*:LOOP\nC:LOOP\n").unwrap();
+    let script = Script::from_file(temp_file.path().to_str().unwrap()).unwrap();
+    let mut executor = Executor::with_backend(script, Box::new(MemoryBackend::new()));
+    
+    let error = loop {
+        if let Err(e) = executor.execute_next() {
+            break e;
+        }
+    };
+    assert!(error.to_string().contains("maximum call depth"), "{}", error);
+    assert_eq!(executor.call_stack.len(), gtypist_rs::script::executor::MAX_CALL_DEPTH);
+}
+
+#[test]
+fn test_trace_records_jump_causes() {
+    use gtypist_rs::runner;
+    use gtypist_rs::script::trace::{self, JumpCause, TraceEvent, TraceSink};
+    
+    let script_content = r#"*:TOP
+K:2:TOP
+M: "Top"
+ :SUB  "Subroutine"
+ :END  "Quit"
+*:SUB
+C:HELLO
+T:back
+*:END
+X:
+*:HELLO
+T:hello
+R:
+"#;
+    
+    let mut temp_file = NamedTempFile::new().unwrap();
+    temp_file.write_all(script_content.as_bytes()).unwrap();
+    let trace_file = NamedTempFile::new().unwrap();
+    let trace_path = trace_file.path().to_str().unwrap();
+    
+    // Choose the subroutine, leave its tutorial, press F2 at the next one
+    // to come back to the menu, then quit from it
+    let keys = "0 <Enter>\n100 <Space>\n200 <F2>\n300 <Down>\n400 <Enter>\n";
+    let events = runner::parse_key_events(keys).unwrap();
+    let mut executor = Executor::with_backend(Script::from_file(temp_file.path().to_str().unwrap()).unwrap(), Box::new(runner::backend_from_events(events)))
+        .with_trace(TraceSink::create(trace_path).unwrap());
+    let report = runner::run(&mut executor).unwrap();
+    assert_eq!(report.outcome, runner::RunOutcome::Exit);
+    
+    let jumps: Vec<(usize, usize, Option<String>, JumpCause)> = trace::read_trace(trace_path).unwrap().into_iter()
+        .filter_map(|record| match record.event {
+            TraceEvent::Jump { from, to, label, cause } => Some((from, to, label, cause)),
+            _ => None,
+        })
+        .collect();
+    
+    assert_eq!(jumps, vec![
+        (2, 3, Some("SUB".to_string()), JumpCause::Menu),
+        (4, 8, Some("HELLO".to_string()), JumpCause::Call),
+        (10, 5, None, JumpCause::Return),
+        (5, 0, Some("TOP".to_string()), JumpCause::KeyBinding),
+        (2, 6, Some("END".to_string()), JumpCause::Menu),
+    ]);
+}
+
+#[test]
+fn test_failed_drill_prompt_and_retry() {
+    use gtypist_rs::runner;
+    
+    let script_content = r#"*:TOP
+M: "Drills"
+ :HARD  "Hard drill"
+ :END   "Quit"
+*:HARD
+D:abcd
+d:abcd
+*:END
+X:
+"#;
+    
+    let mut temp_file = NamedTempFile::new().unwrap();
+    temp_file.write_all(script_content.as_bytes()).unwrap();
+    let path = temp_file.path().to_str().unwrap();
+    
+    // Fail the drill and repeat it, restart it with Ctrl+R partway, then
+    // pass it; the practice drill after it doesn't block on its error
+    let keys = one_error_keys(&["<Enter>", "abcd", "<r>", "<a>", "<C-r>", "<a>", "<b>", "<c>", "<d>", "<Enter>", "abcd"]);
+    let events = runner::parse_key_events(&keys).unwrap();
+    let mut executor = Executor::with_backend(Script::from_file(path).unwrap(), Box::new(runner::backend_from_events(events)));
+    let report = runner::run(&mut executor).unwrap();
+    
+    assert_eq!(report.labels, vec!["TOP", "HARD", "END"]);
+    assert_eq!(report.outcome, runner::RunOutcome::Exit);
+    assert_eq!(report.results.len(), 3);
+    assert_eq!(report.results[0].errors, 1);
+    assert_eq!(report.results[1].errors, 0);
+    assert_eq!(report.results[2].errors, 1);
+    
+    // Back to the menu from the prompt, then quit from it
+    let keys = one_error_keys(&["<Enter>", "abcd", "<m>", "<Down>", "<Enter>"]);
+    let events = runner::parse_key_events(&keys).unwrap();
+    let mut executor = Executor::with_backend(Script::from_file(path).unwrap(), Box::new(runner::backend_from_events(events)));
+    let report = runner::run(&mut executor).unwrap();
+    
+    assert_eq!(report.labels, vec!["TOP", "HARD", "END"]);
+    assert_eq!(report.outcome, runner::RunOutcome::Exit);
+    assert_eq!(report.results.len(), 1);
+}